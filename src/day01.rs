@@ -1,21 +1,35 @@
+use std::cmp::{Ordering, Reverse};
+use std::io::BufRead;
 use itertools::Itertools;
-use sorted_vec::SortedVec;
 use crate::Day;
-use crate::util::{load_from, Errors};
+use crate::util::{open_from, Errors};
 
 pub struct Day01 {}
 
 impl Day for Day01 {
     fn part_1(&self) -> Result<String, Errors> {
-        let file = load_from("day01a.txt")?;
-        let (first, second) = create_lists(file);
-        Ok(format!("{}", get_distance_sum(first, second)))
+        let lists = ListComparison::parse(open_from("day01a.txt")?)?;
+        Ok(format!("{}", lists.distance_sum(0, 1)))
     }
 
     fn part_2(&self) -> Result<String, Errors> {
-        let file = load_from("day01a.txt")?;
-        let (first, second) = create_lists_unsorted(file);
-        Ok(format!("{}", count_lists(first, second)))
+        let lists = ListComparison::parse(open_from("day01a.txt")?)?;
+        Ok(format!("{}", lists.similarity_score(0, 1)))
+    }
+
+    fn details(&self) -> Option<Result<String, Errors>> {
+        Some(open_from("day01a.txt").and_then(ListComparison::parse).map(|lists| {
+            let sums = lists.all_distance_sums().iter()
+                .map(|((first, second), sum)| format!("columns {} and {}: {}", first, second, sum))
+                .join("\n");
+            let distances = lists.distances(0, 1);
+            let shared = lists.most_frequent_shared(0, 1, 10).iter()
+                .map(|x| format!("{}: {} x {}, scoring {}", x.value, x.first_count, x.second_count, x.score()))
+                .join("\n");
+            format!("{} columns of {} values\ndistance sums:\n{}\nlargest distance {}, {} pairs the same\nmost frequent shared values:\n{}",
+                    lists.column_count(), lists.column(0).len(), sums,
+                    distances.iter().max().unwrap_or(&0), distances.iter().filter(|x| **x == 0).count(), shared)
+        }))
     }
 
     fn create_day() -> Box<dyn Day> where Self: Sized {
@@ -23,50 +37,212 @@ impl Day for Day01 {
     }
 }
 
-fn count_lists(first: Vec<u64>, second: Vec<u64>) -> u64 {
+/// If the spread of values is at most this many times the number of values, we counting sort.
+const COUNTING_SORT_FACTOR: u64 = 4;
+
+/// Any number of equal length columns of numbers, held sorted, that can be compared pairwise.
+///
+/// Nothing we compute depends on which row a value came from, so we throw the row order away
+/// when parsing. Methods taking column indices panic if a column doesn't exist.
+#[derive(Debug, PartialEq)]
+struct ListComparison {
+    columns: Vec<Vec<u64>>
+}
+
+/// How often a value appears in each of two columns.
+#[derive(Debug, PartialEq, Clone)]
+struct SharedValue {
+    value: u64,
+    first_count: usize,
+    second_count: usize
+}
+
+impl SharedValue {
+    /// The contribution of this value to the similarity score.
+    fn score(&self) -> u64 {
+        self.value * self.first_count as u64 * self.second_count as u64
+    }
+}
+
+impl ListComparison {
+    fn parse<R: BufRead>(reader: R) -> Result<ListComparison, Errors> {
+        let mut columns = parse_columns(reader)?;
+        columns.iter_mut().for_each(|x| sort_values(x));
+        Ok(ListComparison { columns })
+    }
+
+    fn column_count(&self) -> usize {
+        self.columns.len()
+    }
+
+    fn column(&self, idx: usize) -> &[u64] {
+        &self.columns[idx]
+    }
+
+    /// The distance between each pair of values when both columns are in order.
+    fn distances(&self, first: usize, second: usize) -> Vec<u64> {
+        self.columns[first].iter().zip(&self.columns[second]).map(|(a, b)| a.abs_diff(*b)).collect()
+    }
+
+    fn distance_sum(&self, first: usize, second: usize) -> u64 {
+        get_distance_sum(&self.columns[first], &self.columns[second])
+    }
+
+    /// Every value that appears in both columns, in ascending order of value. The scores sum to
+    /// the similarity score.
+    fn similarity_breakdown(&self, first: usize, second: usize) -> Vec<SharedValue> {
+        let mut firsts = self.columns[first].chunk_by(|a, b| a == b).peekable();
+        let mut seconds = self.columns[second].chunk_by(|a, b| a == b).peekable();
+        let mut shared: Vec<SharedValue> = Vec::new();
+        while let (Some(a), Some(b)) = (firsts.peek(), seconds.peek()) {
+            match a[0].cmp(&b[0]) {
+                Ordering::Less => { firsts.next(); },
+                Ordering::Greater => { seconds.next(); },
+                Ordering::Equal => {
+                    shared.push(SharedValue { value: a[0], first_count: a.len(), second_count: b.len() });
+                    firsts.next();
+                    seconds.next();
+                }
+            }
+        }
+        shared
+    }
+
+    fn similarity_score(&self, first: usize, second: usize) -> u64 {
+        count_lists(&self.columns[first], &self.columns[second])
+    }
+
+    /// The shared values that pair up most often (that is, the highest product of counts), ties
+    /// broken by the smaller value, limited to `limit` entries.
+    fn most_frequent_shared(&self, first: usize, second: usize, limit: usize) -> Vec<SharedValue> {
+        self.similarity_breakdown(first, second)
+            .into_iter()
+            .sorted_by_key(|x| (Reverse(x.first_count * x.second_count), x.value))
+            .take(limit)
+            .collect()
+    }
+
+    /// The distance sum for every pair of columns, for when there are more than two.
+    fn all_distance_sums(&self) -> Vec<((usize, usize), u64)> {
+        (0..self.columns.len())
+            .tuple_combinations()
+            .map(|(a, b)| ((a, b), self.distance_sum(a, b)))
+            .collect()
+    }
+}
+
+fn count_lists(first: &[u64], second: &[u64]) -> u64 {
     let s_counts = second.iter().counts();
 
     let mut result: u64 = 0;
     for val in first {
-        result += val * s_counts.get(&val).map(usize::clone).map(|x| x as u64).unwrap_or(0);
+        result += val * s_counts.get(val).map(|x| *x as u64).unwrap_or(0);
     }
     result
 }
 
-fn create_lists_unsorted(input: String) -> (Vec<u64>, Vec<u64>) {
-    let mut first: Vec<u64> = Vec::new();
-    let mut second: Vec<u64> = Vec::new();
-    for line in input.lines() {
-        let mut iter = line.trim().split_whitespace();
-        first.push(iter.next().unwrap().parse().unwrap());
-        second.push(iter.next().unwrap().parse().unwrap());
+/// Reads the columns line by line, reusing a single line buffer, so we never hold the whole
+/// input in memory as text. The first line sets how many columns there are. Blank lines are
+/// skipped, anything else that isn't that many numbers is an error.
+fn parse_columns<R: BufRead>(mut reader: R) -> Result<Vec<Vec<u64>>, Errors> {
+    let mut columns: Vec<Vec<u64>> = Vec::new();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut row: Vec<u64> = Vec::new();
+    let mut line_number = 0usize;
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer).map_err(Errors::FileLoadFailed)? == 0 {
+            break;
+        }
+        line_number += 1;
+
+        row.clear();
+        for number in (NumberIter { bytes: &buffer, idx: 0 }) {
+            row.push(number.map_err(|_| Errors::ParseFailed(format!("Line {} contains something other than numbers", line_number)))?);
+        }
+        if row.is_empty() {
+            continue;
+        }
+        if columns.is_empty() {
+            columns = vec![Vec::new(); row.len()];
+        }
+        if row.len() != columns.len() {
+            return Err(Errors::ParseFailed(format!("Line {} has {} numbers, expected {}", line_number, row.len(), columns.len())));
+        }
+        columns.iter_mut().zip(&row).for_each(|(column, value)| column.push(*value));
     }
-    (first, second)
+    Ok(columns)
 }
 
-fn create_lists(input: String) -> (SortedVec<u64>, SortedVec<u64>) {
-    let mut first: SortedVec<u64> = SortedVec::new();
-    let mut second: SortedVec<u64> = SortedVec::new();
-    for line in input.lines() {
-        let mut iter = line.trim().split_whitespace();
-        first.insert(iter.next().unwrap().parse().unwrap());
-        second.insert(iter.next().unwrap().parse().unwrap());
+/// Iterates over the whitespace separated unsigned numbers in a line without allocating.
+struct NumberIter<'a> {
+    bytes: &'a [u8],
+    idx: usize
+}
+
+impl Iterator for NumberIter<'_> {
+    type Item = Result<u64, ()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.idx < self.bytes.len() && self.bytes[self.idx].is_ascii_whitespace() {
+            self.idx += 1;
+        }
+        if self.idx == self.bytes.len() {
+            return None;
+        }
+
+        let mut value: u64 = 0;
+        while self.idx < self.bytes.len() && !self.bytes[self.idx].is_ascii_whitespace() {
+            let byte = self.bytes[self.idx];
+            self.idx += 1;
+            if !byte.is_ascii_digit() {
+                return Some(Err(()));
+            }
+            match value.checked_mul(10).and_then(|x| x.checked_add((byte - b'0') as u64)) {
+                Some(v) => value = v,
+                None => return Some(Err(()))
+            }
+        }
+        Some(Ok(value))
     }
-    (first, second)
 }
 
-fn get_distance_sum(first: SortedVec<u64>, second: SortedVec<u64>) -> u64 {
-    let mut r: u64 = 0;
-    for i in 0..first.len() {
-        r += second[i].abs_diff(first[i]);
+/// Sorts in place, counting values instead if they fall in a small enough range (which the
+/// puzzle input does - five digit numbers).
+fn sort_values(values: &mut [u64]) {
+    let (Some(min), Some(max)) = (values.iter().min(), values.iter().max()) else {
+        return;
+    };
+    let (min, max) = (*min, *max);
+    if max - min > (values.len() as u64).saturating_mul(COUNTING_SORT_FACTOR) {
+        values.sort_unstable();
+        return;
+    }
+
+    let mut counts = vec![0usize; (max - min + 1) as usize];
+    for val in values.iter() {
+        counts[(val - min) as usize] += 1;
+    }
+
+    let mut idx = 0usize;
+    for (offset, count) in counts.into_iter().enumerate() {
+        values[idx..idx + count].fill(min + offset as u64);
+        idx += count;
     }
-    r
+}
+
+fn get_distance_sum(first: &[u64], second: &[u64]) -> u64 {
+    first.iter().zip(second).map(|(a, b)| a.abs_diff(*b)).sum()
 }
 
 #[cfg(test)]
 mod test {
+    use std::time::Instant;
+    use itertools::Itertools;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use sorted_vec::SortedVec;
-    use crate::day01::{count_lists, create_lists, get_distance_sum};
+    use crate::day01::{count_lists, get_distance_sum, parse_columns, ListComparison, SharedValue};
 
     const EXAMPLE1: &str = "3   4\n\
                             4   3\n\
@@ -75,25 +251,138 @@ mod test {
                             3   9\n\
                             3   3";
 
+    /// The original parser, kept as a reference for the benchmark.
+    fn create_lists_sorted_vec(input: &str) -> (SortedVec<u64>, SortedVec<u64>) {
+        let mut first: SortedVec<u64> = SortedVec::new();
+        let mut second: SortedVec<u64> = SortedVec::new();
+        for line in input.lines() {
+            let mut iter = line.split_whitespace();
+            first.insert(iter.next().unwrap().parse().unwrap());
+            second.insert(iter.next().unwrap().parse().unwrap());
+        }
+        (first, second)
+    }
+
+    fn generate_input(lines: usize, max: u64, seed: u64) -> String {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..lines).map(|_| format!("{}   {}", rng.gen_range(0..max), rng.gen_range(0..max))).join("\n")
+    }
+
+    const EXAMPLE_THREE_COLUMNS: &str = "3 4 3\n\
+                                         4 3 1\n\
+                                         2 5 3";
+
+    #[test]
+    fn test_parse_columns() {
+        assert_eq!(parse_columns(EXAMPLE1.as_bytes()).unwrap(), vec![vec![3, 4, 2, 1, 3, 3], vec![4, 3, 5, 3, 9, 3]]);
+        assert_eq!(parse_columns(EXAMPLE_THREE_COLUMNS.as_bytes()).unwrap(), vec![vec![3, 4, 2], vec![4, 3, 5], vec![3, 1, 3]]);
+    }
+
+    #[test]
+    fn test_parse_columns_windows_line_endings_and_blank_lines() {
+        assert_eq!(parse_columns("3   4\r\n\r\n4   3\r\n".as_bytes()).unwrap(), vec![vec![3, 4], vec![4, 3]]);
+    }
+
+    #[test]
+    fn test_parse_columns_bad_line() {
+        assert!(parse_columns("3   4\n4\n".as_bytes()).is_err());
+        assert!(parse_columns("3   4\n4 3 2\n".as_bytes()).is_err());
+        assert!(parse_columns("3   x\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_sort_values() {
+        let lists = ListComparison::parse(EXAMPLE1.as_bytes()).unwrap();
+        assert_eq!(lists.column_count(), 2);
+        assert_eq!(lists.column(0), &[1, 2, 3, 3, 3, 4]);
+        assert_eq!(lists.column(1), &[3, 3, 3, 4, 5, 9]);
+    }
+
+    #[test]
+    fn test_distances() {
+        let lists = ListComparison::parse(EXAMPLE1.as_bytes()).unwrap();
+        assert_eq!(lists.distances(0, 1), vec![2, 1, 0, 1, 2, 5]);
+        assert_eq!(lists.distance_sum(0, 1), 11);
+        assert_eq!(lists.distance_sum(1, 0), 11);
+    }
+
+    #[test]
+    fn test_similarity_breakdown() {
+        let lists = ListComparison::parse(EXAMPLE1.as_bytes()).unwrap();
+        let breakdown = lists.similarity_breakdown(0, 1);
+        assert_eq!(breakdown, vec![
+            SharedValue { value: 3, first_count: 3, second_count: 3 },
+            SharedValue { value: 4, first_count: 1, second_count: 1 },
+        ]);
+        assert_eq!(breakdown.iter().map(SharedValue::score).sum::<u64>(), 31);
+        assert_eq!(lists.similarity_score(0, 1), 31);
+    }
+
     #[test]
-    fn test_create_lists() {
-        let (first, second) = create_lists(EXAMPLE1.to_string());
-        assert_eq!(first.to_vec(), vec![1, 2, 3, 3, 3, 4]);
-        assert_eq!(second.to_vec(), vec![3, 3, 3, 4, 5, 9]);
+    fn test_most_frequent_shared() {
+        let lists = ListComparison::parse("1 1\n1 2\n2 1\n5 5\n5 7\n9 9".as_bytes()).unwrap();
+        assert_eq!(lists.most_frequent_shared(0, 1, 2), vec![
+            SharedValue { value: 1, first_count: 2, second_count: 2 },
+            SharedValue { value: 5, first_count: 2, second_count: 1 },
+        ]);
+        assert_eq!(lists.most_frequent_shared(0, 1, 10).len(), 4);
+    }
+
+    #[test]
+    fn test_three_columns() {
+        let lists = ListComparison::parse(EXAMPLE_THREE_COLUMNS.as_bytes()).unwrap();
+        assert_eq!(lists.all_distance_sums(), vec![((0, 1), 3), ((0, 2), 2), ((1, 2), 5)]);
+        assert_eq!(lists.similarity_score(0, 2), 6);
+    }
+
+    #[test]
+    fn test_sort_values_matches_reference() {
+        // both a narrow range (counting) and a wide range (comparison sort)
+        for max in [100, u64::MAX / 2] {
+            let input = generate_input(10_000, max, max);
+            let lists = ListComparison::parse(input.as_bytes()).unwrap();
+            let (expected_first, expected_second) = create_lists_sorted_vec(&input);
+            assert_eq!(lists.column(0), expected_first.as_slice());
+            assert_eq!(lists.column(1), expected_second.as_slice());
+        }
     }
 
     #[test]
     fn test_get_distance_sum() {
-        let first: SortedVec<u64> = SortedVec::from(vec![1, 2, 3, 3, 3, 4]);
-        let second: SortedVec<u64> = SortedVec::from(vec![3, 3, 3, 4, 5, 9]);
-        assert_eq!(get_distance_sum(first, second), 11);
+        assert_eq!(get_distance_sum(&[1, 2, 3, 3, 3, 4], &[3, 3, 3, 4, 5, 9]), 11);
     }
 
     #[test]
     fn test_count_lists() {
         let first: Vec<u64> = vec![1, 2, 3, 3, 3, 4];
         let second: Vec<u64> = vec![3, 3, 3, 4, 5, 9];
-        assert_eq!(count_lists(first, second), 31);
+        assert_eq!(count_lists(&first, &second), 31);
     }
 
-}
\ No newline at end of file
+    /// Run with `cargo test --release -- --ignored --nocapture bench_` to see the timings.
+    #[test]
+    #[ignore]
+    fn bench_parse_and_sort() {
+        for lines in [10_000, 100_000, 200_000] {
+            let input = generate_input(lines, 100_000, 1);
+
+            let start = Instant::now();
+            let (first, second) = create_lists_sorted_vec(&input);
+            let old_result = get_distance_sum(&first, &second);
+            let old_time = start.elapsed();
+
+            let start = Instant::now();
+            let new_result = ListComparison::parse(input.as_bytes()).unwrap().distance_sum(0, 1);
+            let new_time = start.elapsed();
+
+            assert_eq!(old_result, new_result);
+            println!("{} lines: SortedVec {:?}, streaming {:?}", lines, old_time, new_time);
+        }
+
+        let input = generate_input(5_000_000, 100_000, 2);
+        let start = Instant::now();
+        ListComparison::parse(input.as_bytes()).unwrap().distance_sum(0, 1);
+        println!("5000000 lines: streaming {:?}", start.elapsed());
+    }
+
+}
@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
 use itertools::Itertools;
 use crate::Day;
 use crate::day02::Direction::{Decreasing, Increasing, NotStarted};
@@ -10,13 +11,26 @@ impl Day for Day02 {
     fn part_1(&self) -> Result<String, Errors> {
         let file = load_from("day02a.txt")?;
         let lines = parse_lines(file.as_str());
-        Ok(lines.iter().map(|x| is_safe(x, 1)).filter(|x| *x).count().to_string())
+        let rules = SafetyRules::default();
+        Ok(lines.iter().map(|x| is_safe(x, 1, &rules)).filter(|x| *x).count().to_string())
     }
 
     fn part_2(&self) -> Result<String, Errors> {
         let file = load_from("day02a.txt")?;
         let lines = parse_lines(file.as_str());
-        Ok(lines.iter().map(|x| is_safe(x, 2)).filter(|x| *x).count().to_string())
+        let rules = SafetyRules::default();
+        Ok(lines.iter().map(|x| is_safe(x, 2, &rules)).filter(|x| *x).count().to_string())
+    }
+
+    fn details(&self) -> Option<Result<String, Errors>> {
+        Some(load_from("day02a.txt").map(|file| {
+            let rules = SafetyRules::default();
+            parse_lines(file.as_str()).iter()
+                .map(|x| (x, explain(x, 2, &rules)))
+                .filter(|(_, explanation)| explanation.first_failure.is_some())
+                .map(|(x, explanation)| format!("{}: {}", x.iter().join(" "), explanation))
+                .join("\n")
+        }))
     }
 
     fn create_day() -> Box<dyn Day> where Self: Sized {
@@ -24,7 +38,7 @@ impl Day for Day02 {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 enum Direction {
     NotStarted,
     Increasing,
@@ -41,6 +55,59 @@ impl Direction {
     }
 }
 
+/// What makes a pair of adjacent levels safe.
+#[derive(Debug, PartialEq, Clone)]
+struct SafetyRules {
+    /// The smallest allowed difference between adjacent levels
+    min_step: usize,
+    /// The largest allowed difference between adjacent levels
+    max_step: usize,
+    /// Whether the levels must all increase or all decrease
+    monotonic: bool
+}
+
+impl Default for SafetyRules {
+    /// The rules from the puzzle: steps of one to three, all in the same direction.
+    fn default() -> Self {
+        SafetyRules { min_step: 1, max_step: 3, monotonic: true }
+    }
+}
+
+impl SafetyRules {
+    /// The directions a safe report can take. If we don't need to be monotonic, we never set a
+    /// direction, so any step in any direction is fine.
+    fn directions(&self) -> &'static [Direction] {
+        if self.monotonic {
+            &[Increasing, Decreasing]
+        } else {
+            &[NotStarted]
+        }
+    }
+}
+
+/// Why a report is or isn't safe.
+#[derive(Debug, PartialEq)]
+struct Explanation {
+    /// The indices of the first adjacent pair of levels that broke the rules, if any did
+    first_failure: Option<(usize, usize)>,
+    /// The indices of the levels removed to make the report safe (empty if it was already safe),
+    /// or None if it can't be made safe within the tolerance
+    removed: Option<Vec<usize>>
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some((first, second)) = self.first_failure {
+            write!(f, "levels {} and {} fail, ", first, second)?;
+        }
+        match &self.removed {
+            Some(removed) if removed.is_empty() => write!(f, "safe"),
+            Some(removed) => write!(f, "safe after removing level {}", removed.iter().join(", ")),
+            None => write!(f, "unsafe")
+        }
+    }
+}
+
 fn parse_lines(p0: &str) -> Vec<Vec<isize>> {
     let mut lines: Vec<Vec<isize>> = Vec::new();
     for line in p0.lines() {
@@ -49,46 +116,93 @@ fn parse_lines(p0: &str) -> Vec<Vec<isize>> {
     lines
 }
 
-fn is_safe(line: &[isize], tolerance: usize) -> bool {
+/// Whether the report is safe, allowing up to `tolerance - 1` levels to be removed.
+fn is_safe(line: &[isize], tolerance: usize, rules: &SafetyRules) -> bool {
+    min_removals(line, tolerance.saturating_sub(1), rules).is_some()
+}
+
+/// Explains whether the report is safe, allowing up to `tolerance - 1` levels to be removed.
+fn explain(line: &[isize], tolerance: usize, rules: &SafetyRules) -> Explanation {
+    Explanation {
+        first_failure: first_failure(line, rules),
+        removed: find_removals(line, tolerance.saturating_sub(1), rules)
+    }
+}
+
+/// Walks the report as is, returning the first adjacent pair that isn't safe.
+fn first_failure(line: &[isize], rules: &SafetyRules) -> Option<(usize, usize)> {
     let mut current_direction = NotStarted;
-    let mut failures: HashSet<usize> = HashSet::new();
-    for i in 0..(line.len() - 1) {
-        let next = check(line, i, i+1, &current_direction);
-        match next {
-            Some(n) => {
-                current_direction = n;
-            },
-            None => {
-                failures.insert(i);
-                failures.insert(i.saturating_sub(1));
-                if i+1 < line.len() {
-                    failures.insert(i + 1);
-                }
-            },
+    for i in 1..line.len() {
+        match check(line, i - 1, i, &current_direction, rules) {
+            Some(next) if rules.monotonic => current_direction = next,
+            Some(_) => {},
+            None => return Some((i - 1, i))
         }
     }
+    None
+}
 
-    if !failures.is_empty() && tolerance > 1 {
-        for failure in failures {
-            // a little brute force... but it'll do.
-            let mut repaired = line.iter().map(|x| *x).collect_vec();
-            repaired.remove(failure);
-            if is_safe(&repaired, tolerance-1) {
-                return true;
-            }
-        }
-        false
-    } else {
-        failures.is_empty()
+/// The fewest levels that must be removed to make the report safe, if that's no more than
+/// `max_removals`.
+fn min_removals(line: &[isize], max_removals: usize, rules: &SafetyRules) -> Option<usize> {
+    find_removals(line, max_removals, rules).map(|x| x.len())
+}
+
+/// The indices of the fewest levels that must be removed to make the report safe, if that's no
+/// more than `max_removals`.
+fn find_removals(line: &[isize], max_removals: usize, rules: &SafetyRules) -> Option<Vec<usize>> {
+    rules.directions().iter()
+        .filter_map(|direction| find_removals_in_direction(line, max_removals, direction, rules))
+        .min_by_key(|x| x.len())
+}
+
+fn find_removals_in_direction(line: &[isize], max_removals: usize, direction: &Direction, rules: &SafetyRules) -> Option<Vec<usize>> {
+    if line.is_empty() {
+        return Some(vec![]);
+    }
+
+    // removals[i] is the fewest levels removed from the first i levels such that what is left,
+    // ending with level i, is safe, along with the level kept before i. If level p is the one
+    // kept before i, we removed everything between them - so we only need to look back
+    // max_removals + 1 levels, making this O(n.k).
+    let mut removals: Vec<(usize, Option<usize>)> = Vec::with_capacity(line.len());
+    for i in 0..line.len() {
+        let best = (i.saturating_sub(max_removals + 1)..i)
+            .filter(|p| check(line, *p, i, direction, rules).is_some())
+            .map(|p| (removals[p].0 + (i - p - 1), Some(p)))
+            .fold((i, None), |a, b| if b.0 < a.0 { b } else { a });
+        removals.push(best);
+    }
+
+    let (removed, last) = removals.iter()
+        .enumerate()
+        .map(|(i, (removed, _))| (removed + (line.len() - 1 - i), i))
+        .min()?;
+    if removed > max_removals {
+        return None;
+    }
+
+    // walk back through what we kept, everything else was removed.
+    let mut kept = vec![false; line.len()];
+    let mut current = Some(last);
+    while let Some(i) = current {
+        kept[i] = true;
+        current = removals[i].1;
     }
+    Some((0..line.len()).filter(|i| !kept[*i]).collect())
 }
 
-fn check(line: &[isize], idx1: usize, idx2: usize, current_direction: &Direction) -> Option<Direction> {
+fn check(line: &[isize], idx1: usize, idx2: usize, current_direction: &Direction, rules: &SafetyRules) -> Option<Direction> {
     let first = line[idx1];
     let second = line[idx2];
     let r = first.abs_diff(second);
-    let next_direction = if first < second { Increasing } else { Decreasing };
-    if r == 0 || r > 3 || !current_direction.is_safe(&next_direction) {
+    // a repeated level doesn't pick a direction, so it fits either
+    let next_direction = match first.cmp(&second) {
+        Ordering::Less => Increasing,
+        Ordering::Greater => Decreasing,
+        Ordering::Equal => current_direction.clone()
+    };
+    if r < rules.min_step || r > rules.max_step || !current_direction.is_safe(&next_direction) {
         None
     } else {
         Some(next_direction)
@@ -97,9 +211,13 @@ fn check(line: &[isize], idx1: usize, idx2: usize, current_direction: &Direction
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashSet;
+    use itertools::Itertools;
     use lazy_static::lazy_static;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use rstest::rstest;
-    use crate::day02::{is_safe, parse_lines};
+    use crate::day02::{check, explain, is_safe, min_removals, parse_lines, Direction, Explanation, SafetyRules};
 
     const EXAMPLE: &str = "7 6 4 2 1\n\
                            1 2 7 8 9\n\
@@ -136,8 +254,133 @@ mod test {
     #[case(vec![69, 71, 74, 76, 78], true, true)] // taken from list
     #[case(vec![76, 74, 77, 79, 82, 83, 85, 88], false, true)]
     fn test_is_safe(#[case] input: Vec<isize>, #[case] expected_not_tol: bool, #[case] expected_tol: bool) {
-        assert_eq!(is_safe(&input, 1), expected_not_tol, "Zero tolerance, {:?}", input);
-        assert_eq!(is_safe(&input, 2), expected_tol, "One tolerance, {:?}", input);
+        assert_eq!(is_safe(&input, 1, &SafetyRules::default()), expected_not_tol, "Zero tolerance, {:?}", input);
+        assert_eq!(is_safe(&input, 2, &SafetyRules::default()), expected_tol, "One tolerance, {:?}", input);
+    }
+
+    #[rstest]
+    #[case(vec![7, 6, 4, 2, 1], 5, Some(0))]
+    #[case(vec![1, 2, 7, 8, 9], 5, Some(2))]
+    #[case(vec![1, 3, 2, 4, 5], 5, Some(1))]
+    #[case(vec![1, 2, 7, 8, 9], 1, None)]
+    #[case(vec![1, 9, 9, 9, 9, 2, 3], 4, Some(4))]
+    #[case(vec![1, 9, 9, 9, 9, 2, 3], 3, None)]
+    #[case(vec![], 0, Some(0))]
+    #[case(vec![5], 0, Some(0))]
+    #[case(vec![5, 5], 1, Some(1))]
+    fn test_min_removals(#[case] input: Vec<isize>, #[case] max_removals: usize, #[case] expected: Option<usize>) {
+        assert_eq!(min_removals(&input, max_removals, &SafetyRules::default()), expected);
+    }
+
+    #[rstest]
+    #[case(vec![1, 2, 7, 8, 9], SafetyRules { min_step: 1, max_step: 5, monotonic: true }, true)]
+    #[case(vec![1, 3, 2, 4, 5], SafetyRules { min_step: 1, max_step: 3, monotonic: false }, true)]
+    #[case(vec![8, 6, 4, 4, 1], SafetyRules { min_step: 0, max_step: 3, monotonic: true }, true)]
+    #[case(vec![1, 2, 2, 3], SafetyRules { min_step: 0, max_step: 3, monotonic: true }, true)]
+    #[case(vec![2, 2, 3, 1], SafetyRules { min_step: 0, max_step: 3, monotonic: true }, false)]
+    #[case(vec![1, 2, 2, 3], SafetyRules::default(), false)]
+    #[case(vec![1, 3, 6, 7, 9], SafetyRules { min_step: 2, max_step: 3, monotonic: true }, false)]
+    #[case(vec![1, 3, 6, 8, 11], SafetyRules { min_step: 2, max_step: 3, monotonic: true }, true)]
+    fn test_is_safe_custom_rules(#[case] input: Vec<isize>, #[case] rules: SafetyRules, #[case] expected: bool) {
+        assert_eq!(is_safe(&input, 1, &rules), expected);
+    }
+
+    #[rstest]
+    #[case(vec![7, 6, 4, 2, 1], 2, Explanation { first_failure: None, removed: Some(vec![]) })]
+    #[case(vec![1, 2, 7, 8, 9], 2, Explanation { first_failure: Some((1, 2)), removed: None })]
+    #[case(vec![1, 2, 7, 8, 9], 3, Explanation { first_failure: Some((1, 2)), removed: Some(vec![0, 1]) })]
+    #[case(vec![1, 3, 2, 4, 5], 2, Explanation { first_failure: Some((1, 2)), removed: Some(vec![2]) })]
+    #[case(vec![8, 6, 4, 4, 1], 2, Explanation { first_failure: Some((2, 3)), removed: Some(vec![3]) })]
+    #[case(vec![8, 6, 4, 4, 1], 1, Explanation { first_failure: Some((2, 3)), removed: None })]
+    fn test_explain(#[case] input: Vec<isize>, #[case] tolerance: usize, #[case] expected: Explanation) {
+        assert_eq!(explain(&input, tolerance, &SafetyRules::default()), expected);
+    }
+
+    #[test]
+    fn test_explanation_display() {
+        let rules = SafetyRules::default();
+        assert_eq!(explain(&[7, 6, 4, 2, 1], 2, &rules).to_string(), "safe");
+        assert_eq!(explain(&[1, 2, 7, 8, 9], 2, &rules).to_string(), "levels 1 and 2 fail, unsafe");
+        assert_eq!(explain(&[1, 3, 2, 4, 5], 2, &rules).to_string(), "levels 1 and 2 fail, safe after removing level 2");
+    }
+
+    /// The original checker, kept to cross check against.
+    fn is_safe_brute_force(line: &[isize], tolerance: usize) -> bool {
+        let mut current_direction = Direction::NotStarted;
+        let mut failures: HashSet<usize> = HashSet::new();
+        for i in 0..(line.len() - 1) {
+            match check(line, i, i+1, &current_direction, &SafetyRules::default()) {
+                Some(n) => { current_direction = n; },
+                None => {
+                    failures.insert(i);
+                    failures.insert(i.saturating_sub(1));
+                    failures.insert(i + 1);
+                },
+            }
+        }
+
+        if !failures.is_empty() && tolerance > 1 {
+            failures.into_iter().any(|failure| {
+                let mut repaired = line.to_vec();
+                repaired.remove(failure);
+                is_safe_brute_force(&repaired, tolerance - 1)
+            })
+        } else {
+            failures.is_empty()
+        }
+    }
+
+    /// Tries every way of removing levels, for when the brute force checker's shortcuts aren't
+    /// trustworthy. This checks the steps directly rather than through [check].
+    fn is_safe_exhaustive(line: &[isize], max_removals: usize, rules: &SafetyRules) -> bool {
+        (0..=max_removals.min(line.len())).any(|removals| {
+            (0..line.len()).combinations(line.len() - removals).any(|kept| {
+                let steps = kept.iter().tuple_windows().map(|(a, b)| line[*b] - line[*a]).collect_vec();
+                steps.iter().all(|x| (rules.min_step..=rules.max_step).contains(&x.unsigned_abs()))
+                    && (!rules.monotonic || steps.iter().all(|x| *x >= 0) || steps.iter().all(|x| *x <= 0))
+            })
+        })
+    }
+
+    fn random_report(rng: &mut StdRng) -> Vec<isize> {
+        let length = rng.gen_range(2..10);
+        let mut current: isize = rng.gen_range(1..100);
+        let direction = if rng.gen_bool(0.5) { 1 } else { -1 };
+        (0..length).map(|_| {
+            // mostly safe steps, with the odd bad one thrown in
+            current += if rng.gen_bool(0.8) { direction * rng.gen_range(0..=3) } else { rng.gen_range(-6..=6) };
+            current
+        }).collect()
+    }
+
+    #[test]
+    fn test_is_safe_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..5000 {
+            let report = random_report(&mut rng);
+            for tolerance in 1..=2 {
+                assert_eq!(is_safe(&report, tolerance, &SafetyRules::default()), is_safe_brute_force(&report, tolerance), "{:?}, tolerance {}", report, tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_safe_matches_exhaustive() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let rule_sets = [
+            SafetyRules::default(),
+            SafetyRules { min_step: 0, max_step: 3, monotonic: true },
+            SafetyRules { min_step: 0, max_step: 2, monotonic: false },
+            SafetyRules { min_step: 2, max_step: 4, monotonic: true },
+        ];
+        for _ in 0..2000 {
+            let report = random_report(&mut rng);
+            for rules in &rule_sets {
+                for max_removals in 0..=4 {
+                    assert_eq!(is_safe(&report, max_removals + 1, rules), is_safe_exhaustive(&report, max_removals, rules), "{:?}, removals {}, {:?}", report, max_removals, rules);
+                }
+            }
+        }
     }
 
 }
\ No newline at end of file
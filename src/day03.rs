@@ -1,23 +1,32 @@
-use itertools::Itertools;
-use regex::{Captures, Regex};
+use std::io::{BufRead, IsTerminal};
+use std::ops::Range;
 use crate::Day;
-use crate::util::{load_from, Errors};
+use crate::util::{load_from, open_from, Errors};
 
 pub struct Day03 {}
 
 impl Day for Day03 {
     fn part_1(&self) -> Result<String, Errors> {
-        let file = load_from("day03a.txt")?;
-        let couples = scan_string(file.as_str());
-        let result = mul_sum(&couples);
-        Ok(result.to_string())
+        let scanner = scan_file("day03a.txt")?;
+        Ok(scanner.total.to_string())
     }
 
     fn part_2(&self) -> Result<String, Errors> {
-        let file = load_from("day03a.txt")?;
-        let ins = scan_enable_string(file.as_str());
-        let result = mul_sum_enable(&ins);
-        Ok(result.to_string())
+        let scanner = scan_file("day03a.txt")?;
+        Ok(scanner.enabled_total.to_string())
+    }
+
+    fn details(&self) -> Option<Result<String, Errors>> {
+        let highlight = if std::io::stdout().is_terminal() { Highlight::Ansi } else { Highlight::Brackets };
+        Some(load_from("day03a.txt").and_then(|file| {
+            // the interpreter works from the tokens, so says how much of the input was corrupted
+            let mut part_1 = MulMachine::default();
+            puzzle_interpreter(false).run(file.as_bytes(), &mut part_1)?;
+            let mut part_2 = MulMachine::default();
+            let skipped = puzzle_interpreter(true).run(file.as_bytes(), &mut part_2)?;
+            Ok(format!("interpreted: part 1 {}, part 2 {}, skipping {} corrupted spans ({} bytes)\n{}",
+                       part_1.total, part_2.total, skipped.len(), skipped.iter().map(|x| x.len()).sum::<usize>(), annotate(&file, highlight)?))
+        }))
     }
 
     fn create_day() -> Box<dyn Day> where Self: Sized {
@@ -25,8 +34,24 @@ impl Day for Day03 {
     }
 }
 
-const MUL_REGEX: &str = r"mul\((?<first>\d+),(?<second>\d+)\)";
-const ENABLE_MUL_REGEX: &str = r"(?<enable>do\(\))|(?<disable>don\'t\(\))|mul\((?<first>\d+),(?<second>\d+)\)";
+/// Streams the file through a [Scanner] a buffer at a time.
+fn scan_file(filename: &str) -> Result<Scanner, Errors> {
+    let mut reader = open_from(filename)?;
+    let mut scanner = Scanner::default();
+    loop {
+        let chunk = reader.fill_buf().map_err(Errors::FileLoadFailed)?;
+        if chunk.is_empty() {
+            return Ok(scanner);
+        }
+        scanner.feed(chunk)?;
+        let length = chunk.len();
+        reader.consume(length);
+    }
+}
+
+const MUL: Signature = Signature { name: "mul", arity: 2 };
+const ENABLE: Signature = Signature { name: "do", arity: 0 };
+const DISABLE: Signature = Signature { name: "don't", arity: 0 };
 
 #[derive(Debug, PartialEq)]
 enum Instruction {
@@ -35,61 +60,514 @@ enum Instruction {
     Mul(isize, isize)
 }
 
-fn mul_sum(vals: &Vec<(isize, isize)>) -> isize {
-    vals.iter().map(|(a, b)| a * b).sum()
+impl Instruction {
+    fn from_call(name: &str, args: &[isize]) -> Option<Instruction> {
+        match (name, args) {
+            ("do", []) => Some(Instruction::Enable),
+            ("don't", []) => Some(Instruction::Disable),
+            ("mul", [a, b]) => Some(Instruction::Mul(*a, *b)),
+            _ => None
+        }
+    }
 }
 
-fn mul_sum_enable(vals: &Vec<Instruction>) -> isize {
-    let mut enabled = true;
-    let mut result = 0isize;
-    for inst in vals {
-        match inst {
-            Instruction::Enable => { enabled = true; },
-            Instruction::Disable => { enabled = false; },
-            Instruction::Mul(a, b) if enabled => { result = result + a*b },
-            _ => {}
-        }
-    }
-    result
-}
-
-fn scan_string(string: &str) -> Vec<(isize, isize)> {
-    let regex = Regex::new(MUL_REGEX).unwrap();
-    regex
-        .captures_iter(string)
-        .map(|x| (
-            extract_capture(&x, "first"),
-            extract_capture(&x, "second")
-        ))
-        .collect_vec()
-}
-
-fn scan_enable_string(string: &str) -> Vec<Instruction> {
-    let regex = Regex::new(ENABLE_MUL_REGEX).unwrap();
-    regex
-        .captures_iter(string)
-        .map(|x| {
-            if x.name("enable").is_some() {
-                Instruction::Enable
-            } else if x.name("disable").is_some() {
-                Instruction::Disable
+/// The name of an instruction and how many arguments it takes, so it can be recognised as
+/// `name(1,2,...)`, arguments being unsigned integers.
+#[derive(Debug, PartialEq, Clone)]
+struct Signature {
+    name: &'static str,
+    arity: usize
+}
+
+/// Something found in the input, along with the byte offsets it covers.
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    /// A well-formed instruction matching one of the signatures we were asked to look for
+    Call { name: &'static str, args: Vec<isize>, span: Range<usize> },
+    /// A run of input between calls that isn't any instruction
+    Corrupted(Range<usize>)
+}
+
+/// Lazily splits the input into calls and the corrupted spans between them, reading it a buffer
+/// at a time.
+///
+/// Like the puzzle, a call can be preceded by anything, so `undo()` contains `do()`. If more than
+/// one signature matches at the same place, the first given wins.
+struct Tokenizer<'a, R> {
+    source: R,
+    signatures: &'a [Signature],
+    /// What's been read but not yet tokenized, which can be the start of a call split across reads
+    buffer: Vec<u8>,
+    /// The offset into the input of the start of the buffer
+    offset: usize,
+    /// The offset into the input we're looking for a call at
+    position: usize,
+    /// Whether the whole input has been read
+    exhausted: bool,
+    pending: Option<Token>
+}
+
+/// Why there's no call at a position.
+#[derive(Debug, PartialEq)]
+enum Miss {
+    No,
+    /// The buffer ends part way through what could be a call
+    NeedMore
+}
+
+impl<'a, R: BufRead> Tokenizer<'a, R> {
+    fn new(source: R, signatures: &'a [Signature]) -> Tokenizer<'a, R> {
+        Tokenizer { source, signatures, buffer: Vec::new(), offset: 0, position: 0, exhausted: false, pending: None }
+    }
+
+    /// Drops what's before the position from the buffer and reads some more onto the end.
+    fn fill(&mut self) -> Result<(), Errors> {
+        self.buffer.drain(..self.position - self.offset);
+        self.offset = self.position;
+        let chunk = self.source.fill_buf().map_err(Errors::FileLoadFailed)?;
+        if chunk.is_empty() {
+            self.exhausted = true;
+            return Ok(());
+        }
+        self.buffer.extend_from_slice(chunk);
+        let length = chunk.len();
+        self.source.consume(length);
+        Ok(())
+    }
+
+    /// Tries to match a call starting at the given byte of the buffer, returning it if there is
+    /// one. If an earlier signature needs more input to tell, so does this.
+    fn match_at(&self, start: usize) -> Result<Token, Miss> {
+        for signature in self.signatures {
+            match self.match_signature(signature, start) {
+                Err(Miss::No) => {},
+                result => return result
+            }
+        }
+        Err(Miss::No)
+    }
+
+    fn match_signature(&self, signature: &Signature, start: usize) -> Result<Token, Miss> {
+        let mut idx = start;
+        self.expect(&mut idx, signature.name.as_bytes())?;
+        self.expect(&mut idx, b"(")?;
+        let mut args: Vec<isize> = Vec::with_capacity(signature.arity);
+        for arg in 0..signature.arity {
+            if arg > 0 {
+                self.expect(&mut idx, b",")?;
+            }
+            args.push(self.number(&mut idx)?);
+        }
+        self.expect(&mut idx, b")")?;
+        Ok(Token::Call { name: signature.name, args, span: self.offset + start..self.offset + idx })
+    }
+
+    fn expect(&self, idx: &mut usize, expected: &[u8]) -> Result<(), Miss> {
+        let available = &self.buffer[*idx..];
+        if available.starts_with(expected) {
+            *idx += expected.len();
+            Ok(())
+        } else if !self.exhausted && expected.starts_with(available) {
+            Err(Miss::NeedMore)
+        } else {
+            Err(Miss::No)
+        }
+    }
+
+    fn number(&self, idx: &mut usize) -> Result<isize, Miss> {
+        let digits = self.buffer[*idx..].iter().take_while(|x| x.is_ascii_digit()).count();
+        if !self.exhausted && *idx + digits == self.buffer.len() {
+            return Err(Miss::NeedMore);
+        }
+        if digits == 0 {
+            return Err(Miss::No);
+        }
+        let value = self.buffer[*idx..*idx + digits].iter()
+            .try_fold(0isize, |acc, digit| acc.checked_mul(10)?.checked_add((digit - b'0') as isize))
+            .ok_or(Miss::No)?;
+        *idx += digits;
+        Ok(value)
+    }
+}
+
+impl<R: BufRead> Iterator for Tokenizer<'_, R> {
+    type Item = Result<Token, Errors>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(pending) = self.pending.take() {
+            return Some(Ok(pending));
+        }
+
+        let start = self.position;
+        loop {
+            let at = self.position - self.offset;
+            let found = if at == self.buffer.len() {
+                if self.exhausted {
+                    break;
+                }
+                Err(Miss::NeedMore)
             } else {
-                Instruction::Mul(
-                    extract_capture(&x, "first"),
-                    extract_capture(&x, "second")
-                )
+                self.match_at(at)
+            };
+            match found {
+                Ok(call) => {
+                    let call_start = self.position;
+                    if let Token::Call { span, .. } = &call {
+                        self.position = span.end;
+                    }
+                    return Some(Ok(if call_start > start {
+                        self.pending = Some(call);
+                        Token::Corrupted(start..call_start)
+                    } else {
+                        call
+                    }));
+                },
+                Err(Miss::NeedMore) => if let Err(e) = self.fill() {
+                    return Some(Err(e));
+                },
+                Err(Miss::No) => self.position += 1
+            }
+        }
+
+        if start < self.position {
+            Some(Ok(Token::Corrupted(start..self.position)))
+        } else {
+            None
+        }
+    }
+}
+
+/// Runs instructions found in the input against a machine state of our choosing. Each
+/// instruction is registered with a function that updates the state, so anything beyond
+/// arithmetic (like `do()`/`don't()` or conditional blocks) is up to the state to track.
+struct Interpreter<S> {
+    signatures: Vec<Signature>,
+    operations: Vec<Operation<S>>
+}
+
+/// Updates the machine state for an instruction, given its arguments.
+type Operation<S> = fn(&mut S, &[isize]) -> Result<(), Errors>;
+
+impl<S> Interpreter<S> {
+    fn new() -> Interpreter<S> {
+        Interpreter { signatures: Vec::new(), operations: Vec::new() }
+    }
+
+    fn with_instruction(mut self, name: &'static str, arity: usize, operation: Operation<S>) -> Interpreter<S> {
+        self.signatures.push(Signature { name, arity });
+        self.operations.push(operation);
+        self
+    }
+
+    fn tokenize<R: BufRead>(&self, input: R) -> Tokenizer<'_, R> {
+        Tokenizer::new(input, &self.signatures)
+    }
+
+    /// Runs every instruction in the input in order, returning the corrupted spans that were
+    /// skipped over, or the first error from reading the input or running an instruction.
+    fn run<R: BufRead>(&self, input: R, state: &mut S) -> Result<Vec<Range<usize>>, Errors> {
+        let mut skipped: Vec<Range<usize>> = Vec::new();
+        for token in self.tokenize(input) {
+            match token? {
+                Token::Call { name, args, .. } => {
+                    // names are unique in practice, the first registered wins as with tokenizing
+                    let idx = self.signatures.iter().position(|x| x.name == name && x.arity == args.len()).unwrap();
+                    (self.operations[idx])(state, &args)?;
+                },
+                Token::Corrupted(span) => skipped.push(span)
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+/// The machine from the puzzle: multiplications are summed while enabled.
+#[derive(Debug, PartialEq)]
+struct MulMachine {
+    enabled: bool,
+    total: isize
+}
+
+impl Default for MulMachine {
+    fn default() -> Self {
+        MulMachine { enabled: true, total: 0 }
+    }
+}
+
+/// An interpreter for the puzzle's instructions, with or without `do()` and `don't()`.
+fn puzzle_interpreter(with_enable: bool) -> Interpreter<MulMachine> {
+    let interpreter = Interpreter::new()
+        .with_instruction(MUL.name, MUL.arity, |state: &mut MulMachine, args| {
+            if state.enabled {
+                state.total = add_product(state.total, args[0], args[1])?;
             }
-        })
-        .collect_vec()
+            Ok(())
+        });
+    if with_enable {
+        interpreter
+            .with_instruction(ENABLE.name, ENABLE.arity, |state, _| {
+                state.enabled = true;
+                Ok(())
+            })
+            .with_instruction(DISABLE.name, DISABLE.arity, |state, _| {
+                state.enabled = false;
+                Ok(())
+            })
+    } else {
+        interpreter
+    }
 }
 
-fn extract_capture(capture: &Captures, name: &str) -> isize {
-    capture.name(name).map(|x| x.as_str().parse::<isize>()).unwrap().unwrap()
+/// `total + first * second`, or an error if that doesn't fit.
+fn add_product(total: isize, first: isize, second: isize) -> Result<isize, Errors> {
+    first.checked_mul(second)
+        .and_then(|product| total.checked_add(product))
+        .ok_or_else(|| Errors::Overflow(format!("{} + {} * {} doesn't fit", total, first, second)))
+}
+
+/// How far through an instruction the [Scanner] is.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum ScanState {
+    Idle,
+    /// Matched this many bytes of `mul(`
+    Mul(usize),
+    /// Reading the first argument of a `mul`, with its value so far if we've had any digits
+    First(Option<isize>),
+    /// Reading the second argument of a `mul`
+    Second(isize, Option<isize>),
+    /// Matched this many bytes of `do()`
+    Do(usize),
+    /// Matched this many bytes of `don't()`
+    Dont(usize)
+}
+
+const MUL_BYTES: &[u8] = b"mul(";
+const DO_BYTES: &[u8] = b"do()";
+const DONT_BYTES: &[u8] = b"don't()";
+
+/// Works out both parts in a single pass, a byte at a time, without allocating. As all state is
+/// kept between calls to [Scanner::feed], the input can be split anywhere, even mid-instruction.
+///
+/// No instruction contains the first byte of any instruction after its own first byte, so when
+/// a byte breaks a match, that byte is the only place a new match could start.
+#[derive(Debug, PartialEq)]
+struct Scanner {
+    state: ScanState,
+    enabled: bool,
+    /// The sum of all multiplications (part 1)
+    total: isize,
+    /// The sum of multiplications while enabled (part 2)
+    enabled_total: isize
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Scanner { state: ScanState::Idle, enabled: true, total: 0, enabled_total: 0 }
+    }
+}
+
+impl Scanner {
+    /// Scans the next part of the input, or returns an error if a total no longer fits.
+    fn feed(&mut self, chunk: &[u8]) -> Result<(), Errors> {
+        chunk.iter().try_for_each(|byte| self.step(*byte))
+    }
+
+    fn step(&mut self, byte: u8) -> Result<(), Errors> {
+        self.state = match (self.state, byte) {
+            (ScanState::Mul(n), _) if MUL_BYTES[n] == byte => {
+                if n + 1 == MUL_BYTES.len() { ScanState::First(None) } else { ScanState::Mul(n + 1) }
+            },
+            (ScanState::First(value), b'0'..=b'9') => match push_digit(value, byte) {
+                Some(value) => ScanState::First(Some(value)),
+                None => Self::start(byte)
+            },
+            (ScanState::First(Some(first)), b',') => ScanState::Second(first, None),
+            (ScanState::Second(first, value), b'0'..=b'9') => match push_digit(value, byte) {
+                Some(value) => ScanState::Second(first, Some(value)),
+                None => Self::start(byte)
+            },
+            (ScanState::Second(first, Some(second)), b')') => {
+                self.total = add_product(self.total, first, second)?;
+                if self.enabled {
+                    self.enabled_total = add_product(self.enabled_total, first, second)?;
+                }
+                ScanState::Idle
+            },
+            // do() and don't() share the first two bytes
+            (ScanState::Do(2), b'n') => ScanState::Dont(3),
+            (ScanState::Do(n), _) if DO_BYTES[n] == byte => {
+                if n + 1 == DO_BYTES.len() {
+                    self.enabled = true;
+                    ScanState::Idle
+                } else {
+                    ScanState::Do(n + 1)
+                }
+            },
+            (ScanState::Dont(n), _) if DONT_BYTES[n] == byte => {
+                if n + 1 == DONT_BYTES.len() {
+                    self.enabled = false;
+                    ScanState::Idle
+                } else {
+                    ScanState::Dont(n + 1)
+                }
+            },
+            _ => Self::start(byte)
+        };
+        Ok(())
+    }
+
+    /// The state when this byte is not continuing a match.
+    fn start(byte: u8) -> ScanState {
+        match byte {
+            b'm' => ScanState::Mul(1),
+            b'd' => ScanState::Do(1),
+            _ => ScanState::Idle
+        }
+    }
+}
+
+fn push_digit(value: Option<isize>, digit: u8) -> Option<isize> {
+    value.unwrap_or(0).checked_mul(10)?.checked_add((digit - b'0') as isize)
+}
+
+/// Everything in the input, split into the spans that are instructions for part 2 and those that
+/// are not.
+fn enable_tokens(string: &str) -> impl Iterator<Item = Result<(Range<usize>, Option<Instruction>), Errors>> + '_ {
+    Tokenizer::new(string.as_bytes(), &[MUL, ENABLE, DISABLE])
+        .map(|x| x.map(|token| match token {
+            Token::Call { name, args, span } => (span, Instruction::from_call(name, &args)),
+            Token::Corrupted(span) => (span, None)
+        }))
+}
+
+/// How [annotate] marks up the input.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Highlight {
+    /// Colours instructions and dims disabled regions, for terminals
+    Ansi,
+    /// Plain text: `[...]` around instructions that count, `<...>` around disabled `mul`s and
+    /// `{...}` around disabled regions
+    Brackets
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_DIM: &str = "\x1b[2m";
+const ANSI_GREEN: &str = "\x1b[32m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+const ANSI_BLUE: &str = "\x1b[34m";
+const ANSI_RED: &str = "\x1b[31m";
+
+impl Highlight {
+    fn corrupted(&self, text: &str, enabled: bool) -> String {
+        match self {
+            Highlight::Ansi if !enabled => format!("{}{}{}", ANSI_DIM, text, ANSI_RESET),
+            _ => text.to_string()
+        }
+    }
+
+    fn mul(&self, text: &str, running_total: isize) -> String {
+        match self {
+            Highlight::Ansi => format!("{}{}{}{}={}{}", ANSI_GREEN, text, ANSI_RESET, ANSI_YELLOW, running_total, ANSI_RESET),
+            Highlight::Brackets => format!("[{}={}]", text, running_total)
+        }
+    }
+
+    fn disabled_mul(&self, text: &str) -> String {
+        match self {
+            Highlight::Ansi => format!("{}{}{}", ANSI_DIM, text, ANSI_RESET),
+            Highlight::Brackets => format!("<{}>", text)
+        }
+    }
+
+    /// A `do()` or `don't()`. Disabled regions are only opened or closed if this changes whether
+    /// we're enabled, so the brackets balance.
+    fn switch(&self, text: &str, enable: bool, changed: bool) -> String {
+        match self {
+            Highlight::Ansi => format!("{}{}{}", if enable { ANSI_BLUE } else { ANSI_RED }, text, ANSI_RESET),
+            Highlight::Brackets if !changed => format!("[{}]", text),
+            Highlight::Brackets if enable => format!("}}[{}]", text),
+            Highlight::Brackets => format!("[{}]{{", text)
+        }
+    }
+}
+
+/// Renders the input with the instructions [enable_tokens] finds marked up, each `mul`
+/// followed by the running total of part 2 at that point.
+fn annotate(input: &str, highlight: Highlight) -> Result<String, Errors> {
+    let mut output = String::with_capacity(input.len() * 2);
+    let mut enabled = true;
+    let mut total = 0isize;
+    for token in enable_tokens(input) {
+        let (span, instruction) = token?;
+        let text = &input[span];
+        let annotated = match instruction {
+            Some(Instruction::Mul(a, b)) if enabled => {
+                total = add_product(total, a, b)?;
+                highlight.mul(text, total)
+            },
+            Some(Instruction::Mul(_, _)) => highlight.disabled_mul(text),
+            Some(Instruction::Enable) => {
+                let changed = !enabled;
+                enabled = true;
+                highlight.switch(text, true, changed)
+            },
+            Some(Instruction::Disable) => {
+                let changed = enabled;
+                enabled = false;
+                highlight.switch(text, false, changed)
+            },
+            None => highlight.corrupted(text, enabled)
+        };
+        output.push_str(&annotated);
+    }
+    if !enabled && highlight == Highlight::Brackets {
+        output.push('}');
+    }
+    Ok(output)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::day03::{mul_sum, mul_sum_enable, scan_enable_string, scan_string, Instruction};
+    use std::time::Instant;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use regex::Regex;
+    use itertools::Itertools;
+    use std::io::BufReader;
+    use crate::day03::{annotate, enable_tokens, puzzle_interpreter, Highlight, Instruction, Interpreter, MulMachine, Scanner, Token, Tokenizer, DISABLE, ENABLE, MUL};
+    use crate::util::Errors;
+
+    /// The tokenizer path from before the [Scanner], kept as a reference for it.
+    fn mul_sum(vals: &[(isize, isize)]) -> isize {
+        vals.iter().map(|(a, b)| a * b).sum()
+    }
+
+    fn mul_sum_enable(vals: &[Instruction]) -> isize {
+        let mut enabled = true;
+        let mut result = 0isize;
+        for inst in vals {
+            match inst {
+                Instruction::Enable => { enabled = true; },
+                Instruction::Disable => { enabled = false; },
+                Instruction::Mul(a, b) if enabled => { result += a*b },
+                _ => {}
+            }
+        }
+        result
+    }
+
+    fn scan_string(string: &str) -> Vec<(isize, isize)> {
+        Tokenizer::new(string.as_bytes(), &[MUL])
+            .filter_map(|x| match x.unwrap() {
+                Token::Call { args, .. } => Some((args[0], args[1])),
+                Token::Corrupted(_) => None
+            })
+            .collect_vec()
+    }
+
+    fn scan_enable_string(string: &str) -> Vec<Instruction> {
+        enable_tokens(string).filter_map(|x| x.unwrap().1).collect_vec()
+    }
 
     const SAMPLE_DATA: &str = "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
     const SAMPLE_DATA_TWO: &str = "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
@@ -101,7 +579,7 @@ mod tests {
 
     #[test]
     fn test_mul_sum() {
-        assert_eq!(mul_sum(&vec![(2,4), (5,5), (11,8), (8,5)]), 161);
+        assert_eq!(mul_sum(&[(2,4), (5,5), (11,8), (8,5)]), 161);
     }
 
     #[test]
@@ -111,6 +589,244 @@ mod tests {
 
     #[test]
     fn test_mul_sum_enable() {
-        assert_eq!(mul_sum_enable(&vec![Instruction::Mul(2,4), Instruction::Disable, Instruction::Mul(5,5), Instruction::Mul(11,8), Instruction::Enable, Instruction::Mul(8,5)]), 48)
+        assert_eq!(mul_sum_enable(&[Instruction::Mul(2,4), Instruction::Disable, Instruction::Mul(5,5), Instruction::Mul(11,8), Instruction::Enable, Instruction::Mul(8,5)]), 48)
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        assert_eq!(Tokenizer::new("xmul(2,4)%mul[3,7]do()mul(5,5)".as_bytes(), &[MUL, ENABLE]).collect::<Result<Vec<Token>, Errors>>().unwrap(), vec![
+            Token::Corrupted(0..1),
+            Token::Call { name: "mul", args: vec![2, 4], span: 1..9 },
+            Token::Corrupted(9..18),
+            Token::Call { name: "do", args: vec![], span: 18..22 },
+            Token::Call { name: "mul", args: vec![5, 5], span: 22..30 },
+        ]);
+    }
+
+    #[test]
+    fn test_tokenizer_rejects_bad_arguments() {
+        assert_eq!(Tokenizer::new("mul(2,4,6)mul(,1)mul(1 ,2)".as_bytes(), &[MUL]).collect::<Result<Vec<Token>, Errors>>().unwrap(), vec![Token::Corrupted(0..26)]);
+        assert_eq!(Tokenizer::new("".as_bytes(), &[MUL]).count(), 0);
+    }
+
+    #[test]
+    fn test_tokenizer_split_reads() {
+        // calls split across reads, including part way through a number, are still found
+        let whole = Tokenizer::new(SAMPLE_DATA_TWO.as_bytes(), &[MUL, ENABLE, DISABLE]).collect::<Result<Vec<Token>, Errors>>().unwrap();
+        for capacity in 1..SAMPLE_DATA_TWO.len() {
+            let reader = BufReader::with_capacity(capacity, SAMPLE_DATA_TWO.as_bytes());
+            assert_eq!(Tokenizer::new(reader, &[MUL, ENABLE, DISABLE]).collect::<Result<Vec<Token>, Errors>>().unwrap(), whole, "capacity {}", capacity);
+        }
+        let reader = BufReader::with_capacity(2, "mul(12,345)mul(6,78".as_bytes());
+        assert_eq!(Tokenizer::new(reader, &[MUL]).collect::<Result<Vec<Token>, Errors>>().unwrap(), vec![
+            Token::Call { name: "mul", args: vec![12, 345], span: 0..11 },
+            Token::Corrupted(11..19)
+        ]);
+    }
+
+    #[test]
+    fn test_puzzle_interpreter() {
+        let mut state = MulMachine::default();
+        puzzle_interpreter(false).run(SAMPLE_DATA.as_bytes(), &mut state).unwrap();
+        assert_eq!(state.total, 161);
+
+        let mut state = MulMachine::default();
+        let skipped = puzzle_interpreter(true).run(SAMPLE_DATA_TWO.as_bytes(), &mut state).unwrap();
+        assert_eq!(state.total, 48);
+        assert_eq!(skipped[0], 0..1);
+        assert_eq!(skipped.len(), 7);
+    }
+
+    #[test]
+    fn test_interpreter_overflow() {
+        let product = format!("mul({},2)", isize::MAX);
+        assert!(matches!(puzzle_interpreter(false).run(product.as_bytes(), &mut MulMachine::default()), Err(Errors::Overflow(_))));
+        // each product fits, but their sum doesn't
+        let sum = format!("mul({},1)mul(1,1)", isize::MAX);
+        assert!(matches!(puzzle_interpreter(false).run(sum.as_bytes(), &mut MulMachine::default()), Err(Errors::Overflow(_))));
+        assert!(matches!(annotate(&sum, Highlight::Brackets), Err(Errors::Overflow(_))));
+        // disabled, so never multiplied
+        let disabled = format!("don't(){}", product);
+        let mut state = MulMachine::default();
+        puzzle_interpreter(true).run(disabled.as_bytes(), &mut state).unwrap();
+        assert_eq!(state.total, 0);
+    }
+
+    /// A machine with extra arithmetic and nestable `if(n)`/`end()` blocks, that only run what
+    /// they contain if `n` isn't zero.
+    #[derive(Default)]
+    struct BlockMachine {
+        conditions: Vec<bool>,
+        total: isize
+    }
+
+    impl BlockMachine {
+        fn active(&self) -> bool {
+            self.conditions.iter().all(|x| *x)
+        }
+    }
+
+    #[test]
+    fn test_interpreter_extra_instructions() {
+        let interpreter = Interpreter::new()
+            .with_instruction("add", 2, |state: &mut BlockMachine, args| {
+                if state.active() {
+                    state.total += args[0] + args[1];
+                }
+                Ok(())
+            })
+            .with_instruction("sub", 1, |state, args| {
+                if state.active() {
+                    state.total -= args[0];
+                }
+                Ok(())
+            })
+            .with_instruction("if", 1, |state, args| {
+                state.conditions.push(args[0] != 0);
+                Ok(())
+            })
+            .with_instruction("end", 0, |state, _| {
+                state.conditions.pop().map(|_| ()).ok_or_else(|| Errors::BadResult(String::from("end() without if()")))
+            });
+        let mut state = BlockMachine::default();
+        let skipped = interpreter.run("add(1,2)!!if(0)add(10,10)if(1)sub(4)end()end()sub(1)?if(1)add(3,3)end()".as_bytes(), &mut state).unwrap();
+        assert_eq!(state.total, 8);
+        assert_eq!(skipped, vec![8..10, 52..53]);
+        // an instruction's error stops the run
+        assert!(matches!(interpreter.run("end()add(1,1)".as_bytes(), &mut BlockMachine::default()), Err(Errors::BadResult(_))));
+    }
+
+    #[test]
+    fn test_annotate_brackets() {
+        assert_eq!(
+            annotate(SAMPLE_DATA_TWO, Highlight::Brackets).unwrap(),
+            "x[mul(2,4)=8]&mul[3,7]!^[don't()]{_<mul(5,5)>+mul(32,64](<mul(11,8)>un}[do()]?[mul(8,5)=48])"
+        );
+        assert_eq!(annotate("don't()do()do()don't()x", Highlight::Brackets).unwrap(), "[don't()]{}[do()][do()][don't()]{x}");
+    }
+
+    #[test]
+    fn test_annotate_ansi() {
+        assert_eq!(
+            annotate("mul(2,4)don't()xmul(1,1)", Highlight::Ansi).unwrap(),
+            "\x1b[32mmul(2,4)\x1b[0m\x1b[33m=8\x1b[0m\x1b[31mdon't()\x1b[0m\x1b[2mx\x1b[0m\x1b[2mmul(1,1)\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_scanner() {
+        let mut scanner = Scanner::default();
+        scanner.feed(SAMPLE_DATA.as_bytes()).unwrap();
+        assert_eq!(scanner.total, 161);
+        let mut scanner = Scanner::default();
+        scanner.feed(SAMPLE_DATA_TWO.as_bytes()).unwrap();
+        assert_eq!((scanner.total, scanner.enabled_total), (161, 48));
+    }
+
+    #[test]
+    fn test_scanner_overflow() {
+        let mut scanner = Scanner::default();
+        assert!(matches!(scanner.feed(format!("mul({},2)", isize::MAX).as_bytes()), Err(Errors::Overflow(_))));
+        // the sum overflows, with the second product split across chunks
+        let mut scanner = Scanner::default();
+        scanner.feed(format!("mul({},1)mu", isize::MAX).as_bytes()).unwrap();
+        assert!(matches!(scanner.feed(b"l(1,1)"), Err(Errors::Overflow(_))));
+        // part 1 counts disabled products too
+        let mut scanner = Scanner::default();
+        assert!(matches!(scanner.feed(format!("don't()mul({},2)", isize::MAX).as_bytes()), Err(Errors::Overflow(_))));
+    }
+
+    #[test]
+    fn test_scanner_split_anywhere() {
+        let input = SAMPLE_DATA_TWO.as_bytes();
+        for first_split in 0..input.len() {
+            for second_split in first_split..input.len() {
+                let mut scanner = Scanner::default();
+                scanner.feed(&input[..first_split]).unwrap();
+                scanner.feed(&input[first_split..second_split]).unwrap();
+                scanner.feed(&input[second_split..]).unwrap();
+                assert_eq!((scanner.total, scanner.enabled_total), (161, 48), "split at {} and {}", first_split, second_split);
+            }
+        }
+    }
+
+    /// The original regex based implementation, used as a reference.
+    fn regex_totals(input: &str) -> (isize, isize) {
+        let regex = Regex::new(r"(?<enable>do\(\))|(?<disable>don\'t\(\))|mul\((?<first>\d+),(?<second>\d+)\)").unwrap();
+        let mut enabled = true;
+        let (mut total, mut enabled_total) = (0isize, 0isize);
+        for capture in regex.captures_iter(input) {
+            if capture.name("enable").is_some() {
+                enabled = true;
+            } else if capture.name("disable").is_some() {
+                enabled = false;
+            } else {
+                let product = capture["first"].parse::<isize>().unwrap() * capture["second"].parse::<isize>().unwrap();
+                total += product;
+                if enabled {
+                    enabled_total += product;
+                }
+            }
+        }
+        (total, enabled_total)
+    }
+
+    /// Glues together fragments of instructions, never putting two numbers next to each other so
+    /// the products stay small.
+    fn generate_input(fragments: usize, seed: u64) -> String {
+        const PIECES: [&str; 12] = ["mul(", "do()", "don't()", "(", ")", ",", "m", "d", "do", "don'", "x", " "];
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut input = String::new();
+        let mut last_was_number = false;
+        for _ in 0..fragments {
+            if !last_was_number && rng.gen_bool(0.3) {
+                input.push_str(&rng.gen_range(0..1000).to_string());
+                last_was_number = true;
+            } else {
+                input.push_str(PIECES[rng.gen_range(0..PIECES.len())]);
+                last_was_number = false;
+            }
+        }
+        input
+    }
+
+    #[test]
+    fn test_scanner_matches_regex() {
+        for seed in 0..50 {
+            let input = generate_input(2000, seed);
+            let mut scanner = Scanner::default();
+            for chunk in input.as_bytes().chunks(7) {
+                scanner.feed(chunk).unwrap();
+            }
+            assert_eq!((scanner.total, scanner.enabled_total), regex_totals(&input), "seed {}", seed);
+            assert_eq!(scanner.total, mul_sum(&scan_string(&input)), "seed {}", seed);
+            assert_eq!(scanner.enabled_total, mul_sum_enable(&scan_enable_string(&input)), "seed {}", seed);
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_` to see the timings.
+    #[test]
+    #[ignore]
+    fn bench_scanner() {
+        let input = generate_input(2_000_000, 1);
+
+        let start = Instant::now();
+        let regex_result = regex_totals(&input);
+        let regex_time = start.elapsed();
+
+        let start = Instant::now();
+        let tokenizer_result = (mul_sum(&scan_string(&input)), mul_sum_enable(&scan_enable_string(&input)));
+        let tokenizer_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut scanner = Scanner::default();
+        for chunk in input.as_bytes().chunks(8192) {
+            scanner.feed(chunk).unwrap();
+        }
+        let scanner_time = start.elapsed();
+
+        assert_eq!(regex_result, (scanner.total, scanner.enabled_total));
+        assert_eq!(tokenizer_result, (scanner.total, scanner.enabled_total));
+        println!("{} bytes: regex {:?}, tokenizer {:?}, scanner {:?}", input.len(), regex_time, tokenizer_time, scanner_time);
     }
 }
\ No newline at end of file
@@ -1,4 +1,3 @@
-use std::collections::{HashMap, HashSet};
 use std::str::Lines;
 use crate::Day;
 use crate::util::{load_from, Errors};
@@ -8,17 +7,15 @@ pub struct Day04 {}
 impl Day for Day04 {
     fn part_1(&self) -> Result<String, Errors> {
         let file = load_from("day04a.txt")?;
-        let grid = parse_grid(file.lines());
-        let start_points = find_all(&Xmas::X, &grid);
-        let result = check_all_points(&start_points, &grid);
+        let grid: Grid<u8> = Grid::parse(file.lines());
+        let result = WordSearch::new(&["XMAS"]).count(&grid);
         Ok(format!("{}", result))
     }
 
     fn part_2(&self) -> Result<String, Errors> {
         let file = load_from("day04a.txt")?;
-        let grid = parse_grid(file.lines());
-        let start_points = find_all(&Xmas::A, &grid);
-        let result = check_all_x_pairs(&start_points, &grid);
+        let grid: Grid<u8> = Grid::parse(file.lines());
+        let result = StencilSearch::new(&Stencil::parse(X_MAS, b'.'), Symmetry::Rotations).count(&grid);
         Ok(format!("{}", result))
     }
 
@@ -27,16 +24,12 @@ impl Day for Day04 {
     }
 }
 
-type XmasMap = HashMap<(usize, usize), Xmas>;
-
-#[derive(Debug, PartialEq, PartialOrd)]
-enum Xmas {
-    X,
-    M,
-    A,
-    S
-}
+/// Part 2's cross of two "MAS"es, which can be rotated.
+const X_MAS: &str = "M.S\n\
+                     .A.\n\
+                     M.S";
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum Direction {
     Up,
     UpRight,
@@ -60,126 +53,310 @@ impl Direction {
         Direction::UpLeft,
     ];
 
-    fn coords(&self, start: (usize, usize)) -> Option<[(usize, usize); 3]> {
+    /// The (row, col) step to take to move one cell in this direction.
+    fn delta(&self) -> (isize, isize) {
         match *self {
-            Direction::Up => if start.0 > 2 {
-                Some([(start.0 - 1, start.1), (start.0 - 2, start.1), (start.0 - 3, start.1)])
-            } else {
-                None
-            },
-            Direction::UpRight => if start.0 > 2 {
-                Some([(start.0 - 1, start.1 + 1), (start.0 - 2, start.1 + 2), (start.0 - 3, start.1 + 3)])
-            } else {
-                None
-            },
-            Direction::Right => Some([(start.0, start.1 + 1), (start.0, start.1 + 2), (start.0, start.1 + 3)]),
-            Direction::DownRight => Some([(start.0 + 1, start.1 + 1), (start.0 + 2, start.1 + 2), (start.0 + 3, start.1 + 3)]),
-            Direction::Down => Some([(start.0 + 1, start.1), (start.0 + 2, start.1), (start.0 + 3, start.1)]),
-            Direction::DownLeft => if start.1 > 2 {
-                Some([(start.0 + 1, start.1 - 1), (start.0 + 2, start.1 - 2), (start.0 + 3, start.1 - 3)])
-            } else {
-                None
-            },
-            Direction::Left => if start.1 > 2 {
-                Some([(start.0, start.1 - 1), (start.0, start.1 - 2), (start.0, start.1 - 3)])
-            } else {
-                None
-            },
-            Direction::UpLeft => if start.0 > 2 && start.1 > 2 {
-                Some([(start.0 - 1, start.1 - 1), (start.0 - 2, start.1 - 2), (start.0 - 3, start.1 - 3)])
-            } else {
-                None
-            },
+            Direction::Up => (-1, 0),
+            Direction::UpRight => (-1, 1),
+            Direction::Right => (0, 1),
+            Direction::DownRight => (1, 1),
+            Direction::Down => (1, 0),
+            Direction::DownLeft => (1, -1),
+            Direction::Left => (0, -1),
+            Direction::UpLeft => (-1, -1),
         }
     }
 }
 
-fn x_pairs(start: &(usize, usize)) -> Option<[[(usize, usize); 2]; 2]> {
-    if start.0 > 0 && start.1 > 0 {
-        Some([
-            [(start.0 - 1, start.1 - 1), (start.0 + 1, start.1 + 1)],
-            [(start.0 - 1, start.1 + 1), (start.0 + 1, start.1 - 1)]
-        ])
-    } else {
-        None
-    }
+/// Something a [Grid] can be made of, read from text. Bytes are all the puzzle needs, chars let
+/// us search in any alphabet.
+trait Letter: Copy + PartialEq + Default {
+    fn letters(text: &str) -> Vec<Self>;
 }
 
-fn check_x_pair(pairs: &[[(usize, usize); 2]; 2], grid: &XmasMap) -> bool {
-    let [[first_first, first_second], [second_first, second_second]] = *pairs;
-    check_m_and_s(first_first, first_second, grid) && check_m_and_s(second_first, second_second, grid)
+impl Letter for u8 {
+    fn letters(text: &str) -> Vec<Self> {
+        text.bytes().collect()
+    }
 }
 
-fn check_m_and_s(first: (usize, usize), second: (usize, usize), grid: &XmasMap) -> bool {
-    match grid.get(&first) {
-        Some(Xmas::M) => grid.get(&second) == Some(&Xmas::S),
-        Some(Xmas::S) => grid.get(&second) == Some(&Xmas::M),
-        _ => false
+impl Letter for char {
+    fn letters(text: &str) -> Vec<Self> {
+        text.chars().collect()
     }
 }
 
-fn to_xmas(i: char) -> Option<Xmas> {
-    match i {
-        'X' => Some(Xmas::X),
-        'M' => Some(Xmas::M),
-        'A' => Some(Xmas::A),
-        'S' => Some(Xmas::S),
-        _   => None
-    }
+/// A dense grid of letters, stored row by row, indexed by (row, col). Rows shorter than the
+/// longest row are padded with the default letter (zero for bytes).
+#[derive(Debug, PartialEq)]
+struct Grid<T: Letter> {
+    cells: Vec<T>,
+    rows: usize,
+    cols: usize
 }
 
-fn parse_grid(lines: Lines) -> XmasMap {
-    let mut map = HashMap::new();
-    let mut row = 0usize;
-    let mut col = 0usize;
-    for line in lines {
-        for char in line.chars() {
-            if let Some(xmas) = to_xmas(char) {
-                map.insert((row, col), xmas);
-            }
-            col += 1;
+impl<T: Letter> Grid<T> {
+    fn parse(lines: Lines) -> Grid<T> {
+        let lines: Vec<Vec<T>> = lines.map(T::letters).collect();
+        let rows = lines.len();
+        let cols = lines.iter().map(Vec::len).max().unwrap_or(0);
+        let mut cells = vec![T::default(); rows * cols];
+        for (row, line) in lines.iter().enumerate() {
+            cells[row * cols..row * cols + line.len()].copy_from_slice(line);
         }
-        row += 1;
-        col = 0;
+        Grid { cells, rows, cols }
     }
-    map
-}
 
-fn find_all(marker: &Xmas, map: &XmasMap) -> HashSet<(usize, usize)> {
-    map.iter().filter_map(|((x, y), xmas)| {
-        if *xmas == *marker {
-            Some((*x, *y))
+    fn get(&self, coord: (usize, usize)) -> Option<T> {
+        if coord.0 < self.rows && coord.1 < self.cols {
+            Some(self.cells[coord.0 * self.cols + coord.1])
         } else {
             None
         }
-    }).collect()
-}
+    }
 
-fn test_for_xmas(start: (usize, usize), map: &XmasMap) -> usize {
-    Direction::ALL.iter().filter(|x| {
-        if let Some([m, a, s]) = x.coords(start) {
-            map.get(&m) == Some(&Xmas::M) && map.get(&a) == Some(&Xmas::A) && map.get(&s) == Some(&Xmas::S)
+    /// The cell `distance` steps from the given one, if it's on the grid. If we wrap around,
+    /// stepping off one edge brings us back on the opposite edge.
+    fn step(&self, coord: (usize, usize), direction: &Direction, distance: usize, wrap_around: bool) -> Option<(usize, usize)> {
+        let (row_step, col_step) = direction.delta();
+        let row = coord.0 as isize + row_step * distance as isize;
+        let col = coord.1 as isize + col_step * distance as isize;
+        if wrap_around {
+            Some((row.rem_euclid(self.rows as isize) as usize, col.rem_euclid(self.cols as isize) as usize))
+        } else if row >= 0 && col >= 0 && row < self.rows as isize && col < self.cols as isize {
+            Some((row as usize, col as usize))
         } else {
-            false
+            None
+        }
+    }
+}
+
+/// Where a word was found: the index of the word searched for, the cell it starts in, the
+/// direction it reads in and every cell it covers, in order.
+#[derive(Debug, PartialEq, Clone)]
+struct WordMatch {
+    word: usize,
+    start: (usize, usize),
+    direction: Direction,
+    cells: Vec<(usize, usize)>
+}
+
+/// Finds words in a [Grid] reading in any of the eight directions.
+///
+/// Single letter words read the same in every direction, so they are only reported reading
+/// right, once per cell.
+struct WordSearch<T: Letter> {
+    words: Vec<Vec<T>>,
+    wrap_around: bool
+}
+
+#[allow(dead_code)]
+impl<T: Letter> WordSearch<T> {
+    fn new(words: &[&str]) -> WordSearch<T> {
+        WordSearch { words: words.iter().map(|x| T::letters(x)).collect(), wrap_around: false }
+    }
+
+    /// Lets words run off one edge of the grid and continue from the opposite edge.
+    fn with_wrap_around(self, wrap_around: bool) -> WordSearch<T> {
+        WordSearch { wrap_around, ..self }
+    }
+
+    fn find_all(&self, grid: &Grid<T>) -> Vec<WordMatch> {
+        let mut matches: Vec<WordMatch> = Vec::new();
+        self.for_each_match(grid, |word, start, direction| {
+            let cells = (0..self.words[word].len())
+                .filter_map(|distance| grid.step(start, direction, distance, self.wrap_around))
+                .collect();
+            matches.push(WordMatch { word, start, direction: *direction, cells });
+        });
+        matches
+    }
+
+    fn count(&self, grid: &Grid<T>) -> usize {
+        let mut count = 0usize;
+        self.for_each_match(grid, |_, _, _| count += 1);
+        count
+    }
+
+    fn for_each_match<F>(&self, grid: &Grid<T>, mut on_match: F) where F: FnMut(usize, (usize, usize), &Direction) {
+        for row in 0..grid.rows {
+            for col in 0..grid.cols {
+                let first = grid.cells[row * grid.cols + col];
+                for (word, letters) in self.words.iter().enumerate() {
+                    if letters.first() != Some(&first) {
+                        continue;
+                    }
+                    let directions = if letters.len() == 1 { &Direction::ALL[2..3] } else { &Direction::ALL[..] };
+                    for direction in directions {
+                        if self.reads(grid, (row, col), direction, letters) {
+                            on_match(word, (row, col), direction);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the word can be read from the start cell in the given direction.
+    fn reads(&self, grid: &Grid<T>, start: (usize, usize), direction: &Direction, letters: &[T]) -> bool {
+        // without wrapping, if the last letter is on the grid, they all are.
+        if !self.wrap_around && grid.step(start, direction, letters.len() - 1, false).is_none() {
+            return false;
+        }
+        letters.iter()
+            .enumerate()
+            .skip(1)
+            .all(|(distance, letter)| grid.step(start, direction, distance, self.wrap_around).and_then(|x| grid.get(x)) == Some(*letter))
+    }
+}
+
+/// A small rectangular pattern to look for in a [Grid], where `None` matches anything.
+#[derive(Debug, PartialEq, Clone)]
+struct Stencil<T: Letter> {
+    cells: Vec<Vec<Option<T>>>
+}
+
+/// Which transformations of a [Stencil] also count as a match.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Symmetry {
+    /// Only the stencil as given
+    None,
+    /// Any of its quarter turns
+    Rotations,
+    /// Any of its quarter turns, and those of its mirror image
+    RotationsAndReflections
+}
+
+impl<T: Letter> Stencil<T> {
+    /// Parses a stencil, one row per line, with `wildcard` matching anything.
+    fn parse(pattern: &str, wildcard: T) -> Stencil<T> {
+        Stencil {
+            cells: pattern.lines()
+                .map(|line| T::letters(line).into_iter().map(|x| if x == wildcard { None } else { Some(x) }).collect())
+                .collect()
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cols(&self) -> usize {
+        self.cells.iter().map(Vec::len).max().unwrap_or(0)
+    }
+
+    fn get(&self, row: usize, col: usize) -> Option<T> {
+        self.cells.get(row).and_then(|x| x.get(col)).copied().flatten()
+    }
+
+    /// A quarter turn clockwise.
+    fn rotate(&self) -> Stencil<T> {
+        Stencil {
+            cells: (0..self.cols())
+                .map(|col| (0..self.rows()).rev().map(|row| self.get(row, col)).collect())
+                .collect()
+        }
+    }
+
+    /// Mirrored left to right.
+    fn reflect(&self) -> Stencil<T> {
+        let cols = self.cols();
+        Stencil {
+            cells: (0..self.rows())
+                .map(|row| (0..cols).rev().map(|col| self.get(row, col)).collect())
+                .collect()
+        }
+    }
+
+    /// The distinct stencils that count as a match under the given symmetry, so symmetric
+    /// stencils aren't counted more than once in the same place.
+    fn variants(&self, symmetry: Symmetry) -> Vec<Stencil<T>> {
+        let mut bases = vec![self.clone()];
+        if symmetry == Symmetry::RotationsAndReflections {
+            bases.push(self.reflect());
+        }
+
+        let mut variants: Vec<Stencil<T>> = Vec::new();
+        for base in bases {
+            let mut current = base;
+            for _ in 0..(if symmetry == Symmetry::None { 1 } else { 4 }) {
+                let next = current.rotate();
+                if !variants.contains(&current) {
+                    variants.push(current);
+                }
+                current = next;
+            }
         }
-    }).count()
+        variants
+    }
 
+    /// Whether the stencil matches with its top left corner on the given cell.
+    fn matches_at(&self, grid: &Grid<T>, top_left: (usize, usize)) -> bool {
+        if top_left.0 + self.rows() > grid.rows || top_left.1 + self.cols() > grid.cols {
+            return false;
+        }
+        self.cells.iter().enumerate().all(|(row, letters)| {
+            let offset = (top_left.0 + row) * grid.cols + top_left.1;
+            letters.iter().enumerate().all(|(col, letter)| letter.is_none_or(|x| grid.cells[offset + col] == x))
+        })
+    }
 }
 
-fn check_all_points(start_points: &HashSet<(usize, usize)>, grid: &XmasMap) -> usize {
-    start_points.iter().map(|x| test_for_xmas(*x, grid)).sum()
+/// Where a stencil was found: the index of the variant of it that matched (see
+/// [StencilSearch::variants]) and the grid cell under its top left corner.
+#[derive(Debug, PartialEq, Clone)]
+struct StencilMatch {
+    variant: usize,
+    top_left: (usize, usize)
 }
 
-fn check_all_x_pairs(start_points: &HashSet<(usize, usize)>, grid: &XmasMap) -> usize {
-    start_points.iter().filter_map(x_pairs).filter(|x| check_x_pair(x, grid)).count()
+/// Finds every placement of a [Stencil], or its rotations and reflections, in a [Grid].
+struct StencilSearch<T: Letter> {
+    variants: Vec<Stencil<T>>
+}
+
+#[allow(dead_code)]
+impl<T: Letter> StencilSearch<T> {
+    fn new(stencil: &Stencil<T>, symmetry: Symmetry) -> StencilSearch<T> {
+        StencilSearch { variants: stencil.variants(symmetry) }
+    }
+
+    fn variants(&self) -> &[Stencil<T>] {
+        &self.variants
+    }
+
+    fn find_all(&self, grid: &Grid<T>) -> Vec<StencilMatch> {
+        let mut matches: Vec<StencilMatch> = Vec::new();
+        self.for_each_match(grid, |variant, top_left| matches.push(StencilMatch { variant, top_left }));
+        matches
+    }
+
+    fn count(&self, grid: &Grid<T>) -> usize {
+        let mut count = 0usize;
+        self.for_each_match(grid, |_, _| count += 1);
+        count
+    }
+
+    fn for_each_match<F>(&self, grid: &Grid<T>, mut on_match: F) where F: FnMut(usize, (usize, usize)) {
+        for row in 0..grid.rows {
+            for col in 0..grid.cols {
+                for (variant, stencil) in self.variants.iter().enumerate() {
+                    if stencil.matches_at(grid, (row, col)) {
+                        on_match(variant, (row, col));
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::{HashMap, HashSet};
-    use lazy_static::lazy_static;
+    use itertools::Itertools;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use rstest::rstest;
-    use crate::day04::{check_all_x_pairs, find_all, parse_grid, x_pairs, check_x_pair, Xmas, XmasMap};
+    use crate::day04::{Direction, Grid, Stencil, StencilMatch, StencilSearch, Symmetry, WordMatch, WordSearch, X_MAS};
 
     const TEST_GRID: &str = "MMMSXXMASM\n\
                              MSAMXMSMSA\n\
@@ -198,115 +375,180 @@ mod tests {
                                    MSAMA\n\
                                    XMASA";
 
-    lazy_static! {
-        static ref MAPPED_GRID: XmasMap = HashMap::from(
-            [
-                ((0, 0), Xmas::M),
-                ((0, 1), Xmas::M),
-                ((0, 2), Xmas::M),
-                ((0, 3), Xmas::S),
-                ((0, 4), Xmas::X),
-                ((1, 0), Xmas::M),
-                ((1, 1), Xmas::S),
-                ((1, 2), Xmas::A),
-                ((1, 3), Xmas::M),
-                ((1, 4), Xmas::X),
-                ((2, 0), Xmas::A),
-                ((2, 1), Xmas::M),
-                ((2, 2), Xmas::X),
-                ((2, 3), Xmas::S),
-                ((2, 4), Xmas::X),
-                ((3, 0), Xmas::M),
-                ((3, 1), Xmas::S),
-                ((3, 2), Xmas::A),
-                ((3, 3), Xmas::M),
-                ((3, 4), Xmas::A),
-                ((4, 0), Xmas::X),
-                ((4, 1), Xmas::M),
-                ((4, 2), Xmas::A),
-                ((4, 3), Xmas::S),
-                ((4, 4), Xmas::A)
-            ]
-        );
+    #[test]
+    fn test_parse_grid() {
+        let grid: Grid<u8> = Grid::parse(TEST_GRID_PARSE.lines());
+        assert_eq!((grid.rows, grid.cols), (5, 5));
+        assert_eq!(grid.get((0, 0)), Some(b'M'));
+        assert_eq!(grid.get((2, 2)), Some(b'X'));
+        assert_eq!(grid.get((4, 3)), Some(b'S'));
+        assert_eq!(grid.get((5, 0)), None);
+        assert_eq!(grid.get((0, 5)), None);
     }
 
     #[test]
-    fn test_parse_grid() {
-        assert_eq!(*MAPPED_GRID, parse_grid(TEST_GRID_PARSE.lines()))
+    fn test_parse_ragged_grid() {
+        let grid: Grid<u8> = Grid::parse("XM\nXMAS\n".lines());
+        assert_eq!((grid.rows, grid.cols), (2, 4));
+        assert_eq!(grid.cells, b"XM\0\0XMAS".to_vec());
+    }
+
+    #[test]
+    fn test_stencil_rotate_and_reflect() {
+        let stencil = Stencil::parse("AB\nC.", b'.');
+        assert_eq!(stencil.rotate(), Stencil::parse("CA\n.B", b'.'));
+        assert_eq!(stencil.reflect(), Stencil::parse("BA\n.C", b'.'));
+        assert_eq!(Stencil::parse("ABC", b'.').rotate(), Stencil::parse("A\nB\nC", b'.'));
     }
 
     #[rstest]
-    #[case(Xmas::X, Xmas::M, true, false)]
-    #[case(Xmas::X, Xmas::A, true, false)]
-    #[case(Xmas::X, Xmas::S, true, false)]
-    #[case(Xmas::M, Xmas::A, true, false)]
-    #[case(Xmas::M, Xmas::S, true, false)]
-    #[case(Xmas::A, Xmas::S, true, false)]
-    #[case(Xmas::X, Xmas::X, false, false)]
-    #[case(Xmas::M, Xmas::X, false, true)]
-    #[case(Xmas::M, Xmas::M, false, false)]
-    #[case(Xmas::A, Xmas::X, false, true)]
-    #[case(Xmas::A, Xmas::M, false, true)]
-    #[case(Xmas::A, Xmas::A, false, false)]
-    #[case(Xmas::S, Xmas::X, false, true)]
-    #[case(Xmas::S, Xmas::M, false, true)]
-    #[case(Xmas::S, Xmas::A, false, true)]
-    #[case(Xmas::S, Xmas::S, false, false)]
-    fn test_order(#[case] first: Xmas, #[case] second: Xmas, #[case] first_is_less_than: bool, #[case] first_is_greater_than: bool) {
-        assert_eq!(first < second, first_is_less_than);
-        assert_eq!(first > second, first_is_greater_than);
+    #[case(X_MAS, Symmetry::None, 1)]
+    #[case(X_MAS, Symmetry::Rotations, 4)]
+    #[case(X_MAS, Symmetry::RotationsAndReflections, 4)]
+    #[case(".M.\nMAM\n.M.", Symmetry::RotationsAndReflections, 1)]
+    #[case("AB\nC.", Symmetry::RotationsAndReflections, 8)]
+    fn test_stencil_variants(#[case] pattern: &str, #[case] symmetry: Symmetry, #[case] expected: usize) {
+        assert_eq!(Stencil::parse(pattern, b'.').variants(symmetry).len(), expected);
+    }
+
+    #[rstest]
+    #[case((0, 1), true)]
+    #[case((0, 0), false)]
+    #[case((3, 3), false)]
+    #[case((4, 0), false)]
+    fn test_mas_check(#[case] top_left: (usize, usize), #[case] expected: bool) {
+        let grid: Grid<u8> = Grid::parse(TEST_GRID_PARSE.lines());
+        let search = StencilSearch::new(&Stencil::parse(X_MAS, b'.'), Symmetry::Rotations);
+        assert_eq!(search.variants().iter().any(|x| x.matches_at(&grid, top_left)), expected)
     }
 
     #[test]
-    fn test_find_all_for_x() {
-        assert_eq!(find_all(&Xmas::X, &*MAPPED_GRID), HashSet::from([
-            (0, 4),
-            (1, 4),
-            (2, 2),
-            (2, 4),
-            (4, 0),
-        ]))
+    fn test_find_all_stencils() {
+        let grid: Grid<u8> = Grid::parse("M.M.\n.A..\nS.S.\n....".lines());
+        let search = StencilSearch::new(&Stencil::parse(X_MAS, b'.'), Symmetry::Rotations);
+        assert_eq!(search.find_all(&grid), vec![StencilMatch { variant: 1, top_left: (0, 0) }]);
+        assert_eq!(search.variants()[1], Stencil::parse("M.M\n.A.\nS.S", b'.'));
     }
 
     #[test]
-    fn test_find_all_for_a() {
-        assert_eq!(find_all(&Xmas::A, &*MAPPED_GRID), HashSet::from([
-            (1, 2),
-            (2, 0),
-            (3, 2),
-            (3, 4),
-            (4, 2),
-            (4, 4),
-        ]))
+    fn test_part2() {
+        let grid: Grid<u8> = Grid::parse(TEST_GRID.lines());
+        let result = StencilSearch::new(&Stencil::parse(X_MAS, b'.'), Symmetry::Rotations).count(&grid);
+        assert_eq!(result, 9)
     }
 
-    #[rstest]
-    #[case((0, 0), None)]
-    #[case((0, 1), None)]
-    #[case((1, 0), None)]
-    #[case((1, 1), Some([[(0, 0), (2, 2)], [(0, 2), (2, 0)]]))]
-    fn test_x_pairs(#[case] start: (usize, usize), #[case] expected: Option<[[(usize, usize); 2]; 2]>) {
-        assert_eq!(x_pairs(&start), expected)
+    #[test]
+    fn test_part1() {
+        let grid: Grid<u8> = Grid::parse(TEST_GRID.lines());
+        assert_eq!(WordSearch::new(&["XMAS"]).count(&grid), 18)
     }
 
-    #[rstest]
-    #[case((1, 2), true)]
-    #[case((1, 1), false)]
-    fn test_mas_check(#[case] start: (usize, usize), #[case] expected: bool) {
-        if let Some(pairs) = x_pairs(&start) {
-            assert_eq!(check_x_pair(&pairs, &*MAPPED_GRID), expected)
-        } else {
-            panic!("We shouldn't be testing x_pairs")
+    #[test]
+    fn test_find_all_words() {
+        let grid: Grid<u8> = Grid::parse("XMAS\nMMAA\nASXS".lines());
+        let matches = WordSearch::new(&["XMAS", "AM"]).find_all(&grid);
+        assert_eq!(matches, vec![
+            WordMatch { word: 0, start: (0, 0), direction: Direction::Right, cells: vec![(0, 0), (0, 1), (0, 2), (0, 3)] },
+            WordMatch { word: 1, start: (0, 2), direction: Direction::DownLeft, cells: vec![(0, 2), (1, 1)] },
+            WordMatch { word: 1, start: (0, 2), direction: Direction::Left, cells: vec![(0, 2), (0, 1)] },
+            WordMatch { word: 1, start: (1, 2), direction: Direction::Left, cells: vec![(1, 2), (1, 1)] },
+            WordMatch { word: 1, start: (1, 2), direction: Direction::UpLeft, cells: vec![(1, 2), (0, 1)] },
+            WordMatch { word: 1, start: (2, 0), direction: Direction::Up, cells: vec![(2, 0), (1, 0)] },
+            WordMatch { word: 1, start: (2, 0), direction: Direction::UpRight, cells: vec![(2, 0), (1, 1)] },
+        ]);
+    }
+
+    #[test]
+    fn test_wrap_around() {
+        let grid: Grid<u8> = Grid::parse("ASXM\n....".lines());
+        assert_eq!(WordSearch::new(&["XMAS"]).count(&grid), 0);
+        let matches = WordSearch::new(&["XMAS"]).with_wrap_around(true).find_all(&grid);
+        assert_eq!(matches, vec![
+            WordMatch { word: 0, start: (0, 2), direction: Direction::Right, cells: vec![(0, 2), (0, 3), (0, 0), (0, 1)] },
+        ]);
+    }
+
+    #[test]
+    fn test_other_alphabets() {
+        let grid: Grid<char> = Grid::parse("αβγ\nβββ\nγβα".lines());
+        assert_eq!(WordSearch::new(&["αβγ"]).count(&grid), 4);
+        assert_eq!(WordSearch::new(&["β"]).count(&grid), 5);
+        assert_eq!(WordSearch::new(&["αβγδ"]).count(&grid), 0);
+    }
+
+    const LETTERS: [u8; 4] = [b'X', b'M', b'A', b'S'];
+
+    /// Counts a word the obvious way, by spelling out what's in every direction from every cell.
+    fn naive_word_count(grid: &[Vec<u8>], word: &[u8], wrap_around: bool) -> usize {
+        let rows = grid.len() as isize;
+        let cols = grid.first().map(Vec::len).unwrap_or(0) as isize;
+        let mut count = 0;
+        for row in 0..rows {
+            for col in 0..cols {
+                for (row_step, col_step) in [(-1, 0), (-1, 1), (0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1)] {
+                    let spelled: Option<Vec<u8>> = (0..word.len() as isize).map(|distance| {
+                        let (r, c) = (row + row_step * distance, col + col_step * distance);
+                        if wrap_around {
+                            Some(grid[r.rem_euclid(rows) as usize][c.rem_euclid(cols) as usize])
+                        } else if r < 0 || c < 0 || r >= rows || c >= cols {
+                            None
+                        } else {
+                            Some(grid[r as usize][c as usize])
+                        }
+                    }).collect();
+                    if spelled.as_deref() == Some(word) {
+                        count += 1;
+                    }
+                }
+            }
         }
+        count
+    }
 
+    /// Counts X-MASes by checking both diagonals around every A that isn't on an edge.
+    fn naive_x_mas_count(grid: &[Vec<u8>]) -> usize {
+        let mut count = 0;
+        for row in 1..grid.len().saturating_sub(1) {
+            for col in 1..grid[row].len().saturating_sub(1) {
+                let diagonal = |a: u8, b: u8| (a == b'M' && b == b'S') || (a == b'S' && b == b'M');
+                if grid[row][col] == b'A'
+                    && diagonal(grid[row - 1][col - 1], grid[row + 1][col + 1])
+                    && diagonal(grid[row - 1][col + 1], grid[row + 1][col - 1]) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn check_against_naive(cells: &[Vec<u8>]) {
+        let text = cells.iter().map(|x| String::from_utf8_lossy(x)).join("\n");
+        let grid: Grid<u8> = Grid { cells: cells.concat(), rows: cells.len(), cols: cells[0].len() };
+        for word in ["XMAS", "MAS"] {
+            assert_eq!(WordSearch::new(&[word]).count(&grid), naive_word_count(cells, word.as_bytes(), false), "{} in\n{}", word, text);
+            assert_eq!(WordSearch::new(&[word]).with_wrap_around(true).count(&grid), naive_word_count(cells, word.as_bytes(), true), "{} wrapped in\n{}", word, text);
+        }
+        assert_eq!(StencilSearch::new(&Stencil::parse(X_MAS, b'.'), Symmetry::Rotations).count(&grid), naive_x_mas_count(cells), "X-MAS in\n{}", text);
     }
 
     #[test]
-    fn test_part2() {
-        let grid = parse_grid(TEST_GRID.lines());
-        let start_points = find_all(&Xmas::A, &grid);
-        let result = check_all_x_pairs(&start_points, &grid);
-        assert_eq!(result, 9)
+    fn test_every_small_grid_against_naive() {
+        // every grid of these sizes that can be made from X, M, A and S (or without X, for the
+        // bigger grids)
+        for (rows, cols, letters) in [(1, 4, &LETTERS[..]), (4, 1, &LETTERS), (2, 3, &LETTERS), (3, 2, &LETTERS), (3, 3, &LETTERS[1..])] {
+            for cells in (0..rows * cols).map(|_| letters.iter().copied()).multi_cartesian_product() {
+                check_against_naive(&cells.chunks(cols).map(<[u8]>::to_vec).collect_vec());
+            }
+        }
     }
 
+    #[test]
+    fn test_random_grids_against_naive() {
+        let mut rng = StdRng::seed_from_u64(4);
+        for _ in 0..500 {
+            let (rows, cols) = (rng.gen_range(1..12), rng.gen_range(1..12));
+            let cells = (0..rows).map(|_| (0..cols).map(|_| LETTERS[rng.gen_range(0..4)]).collect()).collect_vec();
+            check_against_naive(&cells);
+        }
+    }
 }
\ No newline at end of file
@@ -1,8 +1,9 @@
 use crate::util::{load_from, Errors};
 use crate::Day;
 use core::str::Lines;
-use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
 
@@ -12,8 +13,8 @@ impl Day for Day05 {
     fn part_1(&self) -> Result<String, Errors> {
         let text = load_from("day05a.txt")?;
         let mut lines = text.lines();
-        let ordering = parse_ordering(&mut lines);
-        let updates = parse_update(&mut lines);
+        let ordering = parse_ordering(&mut lines)?;
+        let updates = parse_update(&mut lines)?;
         let result = updates.iter().filter_map(|x| check_order(&ordering, x)).sum::<usize>();
         Ok(format!("{}", result))
     }
@@ -21,14 +22,25 @@ impl Day for Day05 {
     fn part_2(&self) -> Result<String, Errors> {
         let text = load_from("day05a.txt")?;
         let mut lines = text.lines();
-        let ordering = parse_ordering(&mut lines);
+        let ordering = parse_ordering(&mut lines)?;
         // we only want lines that are not valid.
-        let updates: Vec<Vec<usize>> = parse_update(&mut lines).iter().filter(|x| check_order(&ordering, *x).is_none()).map(Vec::clone).collect();
-        let listed = get_listed_numbers(&ordering);
-        let result = updates.iter().map(|x| sort_and_get_middle(&ordering, &listed, x)).sum::<usize>();
+        let updates: Vec<Vec<usize>> = parse_update(&mut lines)?.into_iter().filter(|x| check_order(&ordering, x).is_none()).collect();
+        let result = updates.iter().map(|x| sort_and_get_middle(&ordering, x)).sum::<Result<usize, Errors>>()?;
         Ok(format!("{}", result))
     }
 
+    fn details(&self) -> Option<Result<String, Errors>> {
+        Some(load_from("day05a.txt").and_then(|text| {
+            let mut lines = text.lines();
+            let ordering = parse_ordering(&mut lines)?;
+            let explanations = parse_update(&mut lines)?.iter()
+                .filter(|x| check_order(&ordering, x).is_none())
+                .map(|x| explain_update(&ordering, x).map(|explanation| format!("{}: {}", x.iter().join(","), explanation)))
+                .collect::<Result<Vec<String>, Errors>>()?;
+            Ok(format!("{}\n\n{}", explanations.join("\n"), to_dot(&ordering, None)))
+        }))
+    }
+
     fn create_day() -> Box<dyn Day> where Self: Sized {
         Box::new(Day05 {})
     }
@@ -38,72 +50,428 @@ lazy_static! {
     static ref ORDERING_REGEX: Regex = Regex::new(r"^(\d+)\|(\d+)$").unwrap();
 }
 
-fn parse_ordering(lines: &mut Lines) -> HashMap<(usize, usize), Ordering> {
-    let mut order: HashMap<(usize, usize), Ordering> = HashMap::new();
+/// Page numbers are two digits, so they all fit in a [u128] bitset.
+const PAGES: usize = 100;
+
+/// The ordering rules as an adjacency matrix of bitsets: bit `b` of `after[a]` is set if page `a`
+/// must come before page `b`, and `before` is the same matrix transposed.
+#[derive(Debug, PartialEq, Clone)]
+struct Rules {
+    after: [u128; PAGES],
+    before: [u128; PAGES]
+}
+
+impl Rules {
+    fn new() -> Rules {
+        Rules { after: [0; PAGES], before: [0; PAGES] }
+    }
+
+    #[cfg(test)]
+    fn from_pairs(pairs: &[(usize, usize)]) -> Rules {
+        let mut rules = Rules::new();
+        pairs.iter().for_each(|(first, second)| rules.add(*first, *second).unwrap());
+        rules
+    }
+
+    fn add(&mut self, first: usize, second: usize) -> Result<(), Errors> {
+        if first >= PAGES || second >= PAGES {
+            return Err(Errors::ParseFailed(format!("Pages must be below {}, got {}|{}", PAGES, first, second)));
+        }
+        self.after[first] |= 1 << second;
+        self.before[second] |= 1 << first;
+        Ok(())
+    }
+
+    fn must_precede(&self, first: usize, second: usize) -> bool {
+        self.after[first] & (1 << second) != 0
+    }
+
+    /// Every page that appears in a rule.
+    fn pages(&self) -> u128 {
+        (0..PAGES).filter(|x| self.after[*x] != 0 || self.before[*x] != 0).fold(0, |mask, x| mask | 1 << x)
+    }
+
+    /// Every rule, in page order.
+    fn pairs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..PAGES).flat_map(move |first| pages_in(self.after[first]).map(move |second| (first, second)))
+    }
+}
+
+fn mask_of(pages: &[usize]) -> u128 {
+    pages.iter().fold(0, |mask, x| mask | 1 << x)
+}
+
+/// The pages in a bitset, lowest first.
+fn pages_in(mut mask: u128) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if mask == 0 {
+            None
+        } else {
+            let page = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            Some(page)
+        }
+    })
+}
+
+fn parse_ordering(lines: &mut Lines) -> Result<Rules, Errors> {
+    let mut rules = Rules::new();
     while let Some(Some(m)) = lines.next().map(|x| (*ORDERING_REGEX).find(x)) {
-        let r: (usize, usize) = m
-            .as_str()
-            .split_once("|")
-            .map(|(first, second)| (first.parse().unwrap(), second.parse().unwrap()))
-            .unwrap();
-        order.insert(r, Ordering::Less); // first then second
-        order.insert((r.1, r.0), Ordering::Greater);
+        let (first, second) = m.as_str().split_once("|").unwrap();
+        rules.add(parse_page(first)?, parse_page(second)?)?;
     };
-    order
+    Ok(rules)
 }
 
-fn parse_update(lines: &mut Lines) -> Vec<Vec<usize>> {
+fn parse_update(lines: &mut Lines) -> Result<Vec<Vec<usize>>, Errors> {
     let mut updates: Vec<Vec<usize>> = Vec::new();
-    while let Some(line) = lines.next() {
+    for line in lines {
         if line.contains(",") {
             updates.push(
-                line.split(",").map(|x| x.parse().unwrap()).collect()
+                line.split(",").map(parse_page).collect::<Result<Vec<usize>, Errors>>()?
             );
         }
     };
-    updates
-}
-
-fn check_order(ordering: &HashMap<(usize, usize), Ordering>, vec: &Vec<usize>) -> Option<usize> {
-    let mut is_unspecified = false;
-    for (first_idx, first_value) in vec.iter().enumerate() {
-        let mut inner_unspecified = true;
-        for second_value in vec.iter().skip(first_idx + 1) {
-            match ordering.get(&(*first_value, *second_value)) {
-                Some(Ordering::Less) => { inner_unspecified = false; },
-                Some(Ordering::Greater) => { return None }, // not valid, return immediately
-                _ => {} // status quo
-            }
-        }
-        if is_unspecified && !inner_unspecified {
+    Ok(updates)
+}
+
+/// A page number, which has to fit in the bitsets.
+fn parse_page(page: &str) -> Result<usize, Errors> {
+    page.parse::<usize>().ok()
+        .filter(|x| *x < PAGES)
+        .ok_or_else(|| Errors::ParseFailed(format!("{} isn't a page from 0 to {}", page, PAGES - 1)))
+}
+
+/// Returns the middle page if no page comes after one it must precede, otherwise None.
+fn check_order(rules: &Rules, vec: &[usize]) -> Option<usize> {
+    let mut seen = 0u128;
+    for page in vec {
+        if rules.after[*page] & seen != 0 {
             return None;
         }
-        is_unspecified = inner_unspecified;
+        seen |= 1 << page;
+    }
+    vec.get(vec.len() / 2).copied()
+}
+
+/// How the pages of an update can be ordered, using only the rules between those pages.
+#[derive(Debug, PartialEq)]
+enum TopologicalOrder {
+    /// The only order the rules allow
+    Unique(Vec<usize>),
+    /// An order the rules allow, but not the only one. Where we had a choice, pages are in the
+    /// order they were in the update.
+    Ambiguous(Vec<usize>),
+    /// The rules can't be satisfied, as they make a cycle through these pages (in order, the
+    /// last page having to come before the first), starting from the lowest page
+    Cycle(Vec<usize>)
+}
+
+/// Orders the pages of an update using Kahn's algorithm, over the rules that involve only pages
+/// in the update. A page is ready once none of the pages that must precede it remain.
+fn topological_sort(rules: &Rules, update: &[usize]) -> TopologicalOrder {
+    let mut remaining = mask_of(update);
+    let mut sorted: Vec<usize> = Vec::with_capacity(update.len());
+    let mut ambiguous = false;
+    while remaining != 0 {
+        // take the earliest ready page in update order when there's a choice.
+        let mut ready = update.iter().filter(|x| remaining & 1 << **x != 0 && rules.before[**x] & remaining == 0);
+        let Some(next) = ready.next() else {
+            return TopologicalOrder::Cycle(find_cycle(rules, remaining));
+        };
+        ambiguous |= ready.next().is_some();
+        sorted.push(*next);
+        remaining &= !(1 << next);
+    }
+
+    if ambiguous {
+        TopologicalOrder::Ambiguous(sorted)
+    } else {
+        TopologicalOrder::Unique(sorted)
+    }
+}
+
+/// Finds a cycle among the pages Kahn's algorithm couldn't place.
+fn find_cycle(rules: &Rules, remaining: u128) -> Vec<usize> {
+    // Every page left has a predecessor that's also left, so walking backwards we have to come
+    // back round to a page we've seen.
+    let mut path: Vec<usize> = vec![remaining.trailing_zeros() as usize];
+    loop {
+        let previous = (rules.before[*path.last().unwrap()] & remaining).trailing_zeros() as usize;
+        if let Some(idx) = path.iter().position(|x| *x == previous) {
+            let mut cycle = path.split_off(idx);
+            cycle.reverse();
+            // start from the lowest page, so the same cycle is always reported the same way.
+            let lowest = cycle.iter().enumerate().min_by_key(|(_, x)| **x).unwrap().0;
+            cycle.rotate_left(lowest);
+            return cycle;
+        }
+        path.push(previous);
+    }
+}
+
+fn sort_and_get_middle(rules: &Rules, vec: &[usize]) -> Result<usize, Errors> {
+    // When every pair of pages has exactly one rule between them and each page has a different
+    // number of pages before it, the rules are a total order, and the middle page is the one with
+    // half the pages before it - no sorting needed.
+    let mask = mask_of(vec);
+    let total = vec.len().saturating_sub(1) as u32;
+    let mut counts = 0u128;
+    let mut middle = None;
+    for page in vec {
+        let (before, after) = (rules.before[*page] & mask, rules.after[*page] & mask);
+        let count = before.count_ones();
+        if before & after != 0 || count + after.count_ones() != total || counts & 1 << count != 0 {
+            middle = None;
+            break;
+        }
+        counts |= 1 << count;
+        if count == total / 2 + total % 2 {
+            middle = Some(*page);
+        }
+    }
+    if let Some(middle) = middle {
+        return Ok(middle);
+    }
+
+    match topological_sort(rules, vec) {
+        TopologicalOrder::Unique(sorted) | TopologicalOrder::Ambiguous(sorted) => Ok(sorted[sorted.len() / 2]),
+        TopologicalOrder::Cycle(pages) => Err(Errors::BadResult(format!("Update {:?} can't be ordered, rules make a cycle through {:?}", vec, pages)))
+    }
+}
+
+/// Every rule broken by an update, as (page that should be first, page that should be second),
+/// ordered by the position of the page that should be second, then of the page that should be
+/// first.
+fn find_violations(rules: &Rules, update: &[usize]) -> Vec<(usize, usize)> {
+    update.iter()
+        .enumerate()
+        .flat_map(|(idx, first)| update[idx + 1..].iter().map(move |second| (*second, *first)))
+        .filter(|(first, second)| rules.must_precede(*first, *second))
+        .collect()
+}
+
+/// A rule broken by an update: `first` must come before `second`, but it's at a later position.
+#[derive(Debug, PartialEq)]
+struct Violation {
+    first: usize,
+    second: usize,
+    first_position: usize,
+    second_position: usize
+}
+
+/// Moving a page from one position to another, with positions as they are when the move is made.
+#[derive(Debug, PartialEq)]
+struct Move {
+    page: usize,
+    from: usize,
+    to: usize
+}
+
+/// Why an update is out of order, and how to fix it.
+#[derive(Debug, PartialEq)]
+struct UpdateExplanation {
+    violations: Vec<Violation>,
+    /// The update sorted by [topological_sort], keeping the pages that don't move in their order
+    corrected: Vec<usize>,
+    /// The fewest moves that turn the update into the corrected order, applied in order
+    moves: Vec<Move>
+}
+
+impl Display for UpdateExplanation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.violations.is_empty() {
+            return write!(f, "in order");
+        }
+        let violations = self.violations.iter()
+            .map(|x| format!("{}|{} ({} at {}, {} at {})", x.first, x.second, x.first, x.first_position, x.second, x.second_position))
+            .join(", ");
+        let moves = self.moves.iter().map(|x| format!("{} from {} to {}", x.page, x.from, x.to)).join(", ");
+        write!(f, "breaks {}; corrected to {} by moving {}", violations, self.corrected.iter().join(","), moves)
+    }
+}
+
+/// Explains which rules an update breaks and the fewest moves to put it in order.
+///
+/// The pages to leave alone are the most that can stay in the order they're in (see
+/// [pages_to_keep]), so every other page has to move. The corrected order is sorted keeping those
+/// pages in that order, and each other page is moved to just after the page before it there.
+fn explain_update(rules: &Rules, update: &[usize]) -> Result<UpdateExplanation, Errors> {
+    if let Some(page) = update.iter().duplicates().next() {
+        return Err(Errors::BadResult(format!("Update {:?} has page {} more than once", update, page)));
+    }
+    let violations = find_violations(rules, update).into_iter()
+        .map(|(first, second)| Violation {
+            first,
+            second,
+            first_position: update.iter().position(|x| *x == first).unwrap(),
+            second_position: update.iter().position(|x| *x == second).unwrap()
+        })
+        .collect();
+    if let TopologicalOrder::Cycle(pages) = topological_sort(rules, update) {
+        return Err(Errors::BadResult(format!("Update {:?} can't be ordered, rules make a cycle through {:?}", update, pages)));
+    }
+
+    let kept = pages_to_keep(rules, update);
+    let mut constrained = rules.clone();
+    update.iter().filter(|x| kept & 1 << **x != 0).tuple_windows().try_for_each(|(first, second)| constrained.add(*first, *second))?;
+    let (TopologicalOrder::Unique(corrected) | TopologicalOrder::Ambiguous(corrected)) = topological_sort(&constrained, update) else {
+        return Err(Errors::BadResult(format!("Update {:?} can't keep pages {:?} in order", update, pages_in(kept).collect_vec())));
     };
-    // If we get here, then the sequence is valid, so we find the middle.
-    let middle_idx = vec.iter().count() / 2;
-    vec.get(middle_idx).map(|x| *x)
+
+    let mut current = update.to_vec();
+    let mut moves = Vec::new();
+    for (rank, page) in corrected.iter().enumerate() {
+        if kept & 1 << page != 0 {
+            continue;
+        }
+        let from = current.iter().position(|x| x == page).unwrap();
+        current.remove(from);
+        let to = if rank == 0 { 0 } else { current.iter().position(|x| *x == corrected[rank - 1]).unwrap() + 1 };
+        current.insert(to, *page);
+        moves.push(Move { page: *page, from, to });
+    }
+
+    Ok(UpdateExplanation { violations, corrected, moves })
+}
+
+/// The most pages of an update that can stay in the order they're in, as a bitset.
+///
+/// Two pages can't both stay if the later one has to come before the earlier, following the rules
+/// through the update's pages. Those pairs are a partial order, so the pages that can stay are its
+/// largest antichain. By Dilworth's and König's theorems, that's the pages outside a minimum
+/// vertex cover of the pairs, found from a maximum matching.
+fn pages_to_keep(rules: &Rules, update: &[usize]) -> u128 {
+    let pages = mask_of(update);
+    let reachable: Vec<u128> = update.iter().map(|x| reachable_within(rules, *x, pages)).collect();
+    // `conflicts[i]` are the positions after `i` whose page has to come before page `i`
+    let conflicts: Vec<Vec<usize>> = (0..update.len())
+        .map(|i| (i + 1..update.len()).filter(|j| reachable[*j] & 1 << update[i] != 0).collect())
+        .collect();
+
+    let mut matched: Vec<Option<usize>> = vec![None; update.len()];
+    for i in 0..update.len() {
+        augment(i, &conflicts, &mut matched, &mut vec![false; update.len()]);
+    }
+
+    // the cover is the right side not reached by alternating paths from the unmatched right side,
+    // and the left side that is, and going this way round keeps the earlier pages when there's a
+    // choice
+    let mut partner: Vec<Option<usize>> = vec![None; update.len()];
+    matched.iter().enumerate().for_each(|(j, i)| if let Some(i) = i { partner[*i] = Some(j) });
+    let (mut left, mut right) = (vec![false; update.len()], vec![false; update.len()]);
+    let mut stack: Vec<usize> = (0..update.len()).filter(|x| matched[*x].is_none()).collect();
+    stack.iter().for_each(|x| right[*x] = true);
+    while let Some(j) = stack.pop() {
+        for i in (0..j).filter(|i| conflicts[*i].contains(&j)) {
+            if !left[i] {
+                left[i] = true;
+                if let Some(k) = partner[i].filter(|k| !right[*k]) {
+                    right[k] = true;
+                    stack.push(k);
+                }
+            }
+        }
+    }
+    (0..update.len()).filter(|x| right[*x] && !left[*x]).fold(0, |mask, x| mask | 1 << update[x])
+}
+
+/// Looks for an augmenting path from `i` for Kuhn's matching algorithm, where `matched[j]` is the
+/// position on the left matched to `j` on the right.
+fn augment(i: usize, conflicts: &[Vec<usize>], matched: &mut [Option<usize>], visited: &mut [bool]) -> bool {
+    for j in &conflicts[i] {
+        if !visited[*j] {
+            visited[*j] = true;
+            if matched[*j].is_none_or(|k| augment(k, conflicts, matched, visited)) {
+                matched[*j] = Some(i);
+                return true;
+            }
+        }
+    }
+    false
 }
 
-fn sort_and_get_middle(ordering: &HashMap<(usize, usize), Ordering>, listed: &HashSet<usize>, vec: &Vec<usize>) -> usize {
-    let mut actual_vec = vec.iter().filter(|x| listed.contains(x)).collect::<Vec<&usize>>();
-    actual_vec.sort_by(|first, second| {
-        *ordering.get(&(**first, **second)).unwrap_or(&Ordering::Equal)
-    });
-    *actual_vec[vec.len() / 2]
+/// The rules between the given pages, with any rule that's implied by others removed. A rule is
+/// only removed if its pages are still connected without it, so this works even when the rules
+/// have cycles (where, unlike for a DAG, there's no single smallest answer).
+fn transitive_reduction(rules: &Rules, pages: u128) -> BTreeSet<(usize, usize)> {
+    let mut reduced = Rules::new();
+    for page in pages_in(pages) {
+        reduced.after[page] = rules.after[page] & pages;
+        reduced.before[page] = rules.before[page] & pages;
+    }
+    for (first, second) in reduced.clone().pairs() {
+        reduced.after[first] &= !(1 << second);
+        if !is_reachable(&reduced, first, second) {
+            reduced.after[first] |= 1 << second;
+        }
+    }
+    reduced.pairs().collect()
 }
 
-fn get_listed_numbers(map: &HashMap<(usize, usize), Ordering>) -> HashSet<usize> {
-    map.keys().map(|(first, _)| *first).collect()
+/// The pages that have to come after `from`, following only rules between the given pages.
+fn reachable_within(rules: &Rules, from: usize, pages: u128) -> u128 {
+    let (mut seen, mut frontier) = (0u128, 1u128 << from);
+    while frontier != 0 {
+        let next = pages_in(frontier).fold(0, |mask, x| mask | rules.after[x]) & pages;
+        frontier = next & !seen;
+        seen |= next;
+    }
+    seen
+}
+
+fn is_reachable(rules: &Rules, from: usize, to: usize) -> bool {
+    let mut seen: u128 = 1 << from;
+    let mut frontier: u128 = 1 << from;
+    while frontier != 0 {
+        let next = pages_in(frontier).fold(0, |mask, x| mask | rules.after[x]);
+        if next & 1 << to != 0 {
+            return true;
+        }
+        frontier = next & !seen;
+        seen |= next;
+    }
+    false
+}
+
+/// Renders the rules as a Graphviz DOT graph, an edge from each page to those that must come after
+/// it, transitively reduced so it's readable.
+///
+/// If given an update, only the rules between its pages are drawn, and any rules it breaks are
+/// drawn in red (even if they'd otherwise have been reduced away).
+fn to_dot(rules: &Rules, update: Option<&[usize]>) -> String {
+    let pages = update.map(mask_of).unwrap_or_else(|| rules.pages());
+    let violations: BTreeSet<(usize, usize)> = update.map(|x| find_violations(rules, x)).unwrap_or_default().into_iter().collect();
+    let edges = transitive_reduction(rules, pages);
+
+    let mut dot = String::from("digraph rules {\n");
+    for page in pages_in(pages) {
+        dot.push_str(&format!("    {};\n", page));
+    }
+    for (first, second) in edges.union(&violations) {
+        if violations.contains(&(*first, *second)) {
+            dot.push_str(&format!("    {} -> {} [color=red, penwidth=2];\n", first, second));
+        } else {
+            dot.push_str(&format!("    {} -> {};\n", first, second));
+        }
+    }
+    dot.push('}');
+    dot
 }
 
 #[cfg(test)]
 mod tests {
+    use itertools::Itertools;
     use std::cmp::Ordering;
-    use std::collections::HashMap;
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::time::Instant;
     use lazy_static::lazy_static;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
     use rstest::rstest;
-    use crate::day05::{check_order, get_listed_numbers, parse_ordering, parse_update, sort_and_get_middle};
+    use crate::util::Errors;
+    use crate::day05::{check_order, explain_update, find_violations, mask_of, Move, UpdateExplanation, Violation, parse_ordering, to_dot, transitive_reduction, parse_update, sort_and_get_middle, topological_sort, Rules, TopologicalOrder, PAGES};
 
     const TEST_ENTRY: &str = "47|53\n\
                               97|13\n\
@@ -134,49 +502,28 @@ mod tests {
                               97,13,75,29,47";
 
     lazy_static! {
-        static ref TEST_ORDERING: HashMap<(usize, usize), Ordering> = HashMap::from([
-            ((47,53), Ordering::Less),
-            ((97,13), Ordering::Less),
-            ((97,61), Ordering::Less),
-            ((97,47), Ordering::Less),
-            ((75,29), Ordering::Less),
-            ((61,13), Ordering::Less),
-            ((75,53), Ordering::Less),
-            ((29,13), Ordering::Less),
-            ((97,29), Ordering::Less),
-            ((53,29), Ordering::Less),
-            ((61,53), Ordering::Less),
-            ((97,53), Ordering::Less),
-            ((61,29), Ordering::Less),
-            ((47,13), Ordering::Less),
-            ((75,47), Ordering::Less),
-            ((97,75), Ordering::Less),
-            ((47,61), Ordering::Less),
-            ((75,61), Ordering::Less),
-            ((47,29), Ordering::Less),
-            ((75,13), Ordering::Less),
-            ((53,13), Ordering::Less),
-            ((53,47), Ordering::Greater),
-            ((13,97), Ordering::Greater),
-            ((61,97), Ordering::Greater),
-            ((47,97), Ordering::Greater),
-            ((29,75), Ordering::Greater),
-            ((13,61), Ordering::Greater),
-            ((53,75), Ordering::Greater),
-            ((13,29), Ordering::Greater),
-            ((29,97), Ordering::Greater),
-            ((29,53), Ordering::Greater),
-            ((53,61), Ordering::Greater),
-            ((53,97), Ordering::Greater),
-            ((29,61), Ordering::Greater),
-            ((13,47), Ordering::Greater),
-            ((47,75), Ordering::Greater),
-            ((75,97), Ordering::Greater),
-            ((61,47), Ordering::Greater),
-            ((61,75), Ordering::Greater),
-            ((29,47), Ordering::Greater),
-            ((13,75), Ordering::Greater),
-            ((13,53), Ordering::Greater),
+        static ref TEST_ORDERING: Rules = Rules::from_pairs(&[
+            (47,53),
+            (97,13),
+            (97,61),
+            (97,47),
+            (75,29),
+            (61,13),
+            (75,53),
+            (29,13),
+            (97,29),
+            (53,29),
+            (61,53),
+            (97,53),
+            (61,29),
+            (47,13),
+            (75,47),
+            (97,75),
+            (47,61),
+            (75,61),
+            (47,29),
+            (75,13),
+            (53,13)
         ]);
 
          static ref TEST_DATA: Vec<Vec<usize>> = vec![
@@ -192,9 +539,11 @@ mod tests {
     #[test]
     fn test_parse_entry() {
         let mut lines = TEST_ENTRY.lines();
-        let result = parse_ordering(&mut lines);
-        assert_eq!(result.iter().count(), (*TEST_ORDERING).iter().count());
+        let result = parse_ordering(&mut lines).unwrap();
+        assert_eq!(result.pairs().count(), 21);
         assert_eq!(result, *TEST_ORDERING);
+        assert!(result.must_precede(47, 53));
+        assert!(!result.must_precede(53, 47));
         assert_eq!(lines.next().unwrap(), "75,47,61,53,29");
     }
 
@@ -202,8 +551,8 @@ mod tests {
     fn test_parse_update() {
         let mut l = TEST_ENTRY.lines();
         while l.next().unwrap().contains("|") {} // read lines until we get the newline.
-        let result = parse_update(&mut l);
-        assert_eq!(result.iter().count(), (*TEST_DATA).iter().count());
+        let result = parse_update(&mut l).unwrap();
+        assert_eq!(result.len(), TEST_DATA.len());
         assert_eq!(result, *TEST_DATA)
     }
 
@@ -215,7 +564,7 @@ mod tests {
     #[case(vec![61,13,29], None)]
     #[case(vec![97,13,75,29,47], None)]
     fn test_check_order(#[case] input: Vec<usize>, #[case] expected: Option<usize>) {
-        assert_eq!(check_order(&*TEST_ORDERING, &input), expected);
+        assert_eq!(check_order(&TEST_ORDERING, &input), expected);
     }
 
     #[rstest]
@@ -223,7 +572,289 @@ mod tests {
     #[case(vec![61,13,29], 29)]
     #[case(vec![97,13,75,29,47], 47)]
     fn test_sort(#[case] input: Vec<usize>, #[case] expected: usize) {
-        assert_eq!(sort_and_get_middle(&*TEST_ORDERING, &get_listed_numbers(&*TEST_ORDERING), &input), expected);
+        assert_eq!(sort_and_get_middle(&TEST_ORDERING, &input).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case(vec![75,97,47,61,53], TopologicalOrder::Unique(vec![97,75,47,61,53]))]
+    #[case(vec![61,13,29], TopologicalOrder::Unique(vec![61,29,13]))]
+    #[case(vec![97,13,75,29,47], TopologicalOrder::Unique(vec![97,75,47,29,13]))]
+    #[case(vec![75,47,61,53,29], TopologicalOrder::Unique(vec![75,47,61,53,29]))]
+    #[case(vec![99,75,47], TopologicalOrder::Ambiguous(vec![99,75,47]))]
+    #[case(vec![47,99,75], TopologicalOrder::Ambiguous(vec![99,75,47]))]
+    #[case(vec![], TopologicalOrder::Unique(vec![]))]
+    fn test_topological_sort(#[case] input: Vec<usize>, #[case] expected: TopologicalOrder) {
+        assert_eq!(topological_sort(&TEST_ORDERING, &input), expected);
+    }
+
+    #[test]
+    fn test_topological_sort_sparse_rules() {
+        // 1 before 2 before 3 and nothing else - a comparator would say 1 and 3 are equal
+        let ordering = parse_ordering(&mut "1|2\n2|3".lines()).unwrap();
+        assert_eq!(topological_sort(&ordering, &[3, 1, 2]), TopologicalOrder::Unique(vec![1, 2, 3]));
+        assert_eq!(sort_and_get_middle(&ordering, &[3, 2, 1]).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_topological_sort_cycle() {
+        let ordering = parse_ordering(&mut "1|2\n2|3\n3|4\n4|2\n4|5".lines()).unwrap();
+        assert_eq!(topological_sort(&ordering, &[5, 4, 3, 2, 1]), TopologicalOrder::Cycle(vec![2, 3, 4]));
+        assert_eq!(topological_sort(&ordering, &[1, 3, 5]), TopologicalOrder::Ambiguous(vec![1, 3, 5]));
+        assert!(sort_and_get_middle(&ordering, &[1, 2, 3, 4, 5]).is_err());
+    }
+
+    #[rstest]
+    #[case(vec![75,47,61,53,29], vec![])]
+    #[case(vec![75,97,47,61,53], vec![(97,75)])]
+    #[case(vec![61,13,29], vec![(29,13)])]
+    #[case(vec![97,13,75,29,47], vec![(75,13), (29,13), (47,13), (47,29)])]
+    fn test_find_violations(#[case] input: Vec<usize>, #[case] expected: Vec<(usize, usize)>) {
+        assert_eq!(find_violations(&TEST_ORDERING, &input), expected);
+    }
+
+    #[test]
+    fn test_explain_update() {
+        assert_eq!(
+            explain_update(&TEST_ORDERING, &[75, 97, 47, 61, 53]).unwrap(),
+            UpdateExplanation {
+                violations: vec![Violation { first: 97, second: 75, first_position: 1, second_position: 0 }],
+                corrected: vec![97, 75, 47, 61, 53],
+                moves: vec![Move { page: 97, from: 1, to: 0 }]
+            }
+        );
+        assert_eq!(
+            explain_update(&TEST_ORDERING, &[61, 13, 29]).unwrap().to_string(),
+            "breaks 29|13 (29 at 2, 13 at 1); corrected to 61,29,13 by moving 29 from 2 to 1"
+        );
+        assert_eq!(
+            explain_update(&TEST_ORDERING, &[97, 13, 75, 29, 47]).unwrap().moves,
+            vec![Move { page: 47, from: 4, to: 3 }, Move { page: 13, from: 1, to: 4 }]
+        );
+
+        let explanation = explain_update(&TEST_ORDERING, &[75, 47, 61, 53, 29]).unwrap();
+        assert!(explanation.violations.is_empty() && explanation.moves.is_empty());
+        assert_eq!(explanation.to_string(), "in order");
+
+        let ordering = parse_ordering(&mut "1|2\n2|3\n3|1".lines()).unwrap();
+        assert!(explain_update(&ordering, &[3, 2, 1]).is_err());
+        assert!(matches!(explain_update(&TEST_ORDERING, &[75, 97, 75]), Err(Errors::BadResult(_))));
+    }
+
+    #[test]
+    fn test_explain_update_minimal() {
+        for density in [1.0, 0.6, 0.3, 0.1] {
+            let (rules, updates) = generate_input(12, 300, density, 4);
+            for update in &updates {
+                let explanation = explain_update(&rules, update).unwrap();
+                let mut current = update.clone();
+                for Move { page, from, to } in &explanation.moves {
+                    assert_eq!(current.remove(*from), *page);
+                    current.insert(*to, *page);
+                }
+                assert_eq!(current, explanation.corrected);
+                assert!(find_violations(&rules, &current).is_empty(), "{:?}", update);
+
+                // the most pages that can stay put is the largest subset that can be sorted
+                // without changing its order
+                let in_order = (0u32..1 << update.len())
+                    .filter(|subset| {
+                        let mut constrained = rules.clone();
+                        update.iter().enumerate()
+                            .filter(|(idx, _)| subset & 1 << idx != 0)
+                            .tuple_windows()
+                            .for_each(|((_, first), (_, second))| constrained.add(*first, *second).unwrap());
+                        !matches!(topological_sort(&constrained, update), TopologicalOrder::Cycle(_))
+                    })
+                    .map(|subset| subset.count_ones() as usize)
+                    .max()
+                    .unwrap();
+                assert_eq!(explanation.moves.len(), update.len() - in_order, "{} {:?}", density, update);
+            }
+        }
+    }
+
+    #[test]
+    fn test_transitive_reduction() {
+        // the example's rules are a total order, so only the chain remains
+        let pages = [97, 75, 47, 61, 53, 29, 13];
+        assert_eq!(
+            transitive_reduction(&TEST_ORDERING, mask_of(&pages)),
+            BTreeSet::from([(97, 75), (75, 47), (47, 61), (61, 53), (53, 29), (29, 13)])
+        );
+
+        // with a cycle, every page can still reach every other page
+        let ordering = parse_ordering(&mut "1|2\n2|3\n3|1\n2|4\n1|4".lines()).unwrap();
+        assert_eq!(transitive_reduction(&ordering, mask_of(&[1, 2, 3, 4])), BTreeSet::from([(1, 2), (2, 3), (2, 4), (3, 1)]));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_to_dot() {
+        assert_eq!(
+            to_dot(&TEST_ORDERING, Some(&[61, 13, 29])),
+            "digraph rules {\n    13;\n    29;\n    61;\n    29 -> 13 [color=red, penwidth=2];\n    61 -> 29;\n}"
+        );
+        assert_eq!(
+            to_dot(&TEST_ORDERING, Some(&[97, 13, 75, 29, 47])),
+            "digraph rules {\n    13;\n    29;\n    47;\n    75;\n    97;\n    29 -> 13 [color=red, penwidth=2];\n    47 -> 13 [color=red, penwidth=2];\n    \
+            47 -> 29 [color=red, penwidth=2];\n    75 -> 13 [color=red, penwidth=2];\n    75 -> 47;\n    97 -> 75;\n}"
+        );
+        assert_eq!(to_dot(&TEST_ORDERING, None).lines().filter(|x| x.contains("->")).count(), 6);
+    }
+
+    /// The original HashMap based rules, as built by the original `parse_ordering`, kept as a
+    /// reference for the bitsets.
+    fn parse_ordering_map(rules: &Rules) -> HashMap<(usize, usize), Ordering> {
+        let mut order: HashMap<(usize, usize), Ordering> = HashMap::new();
+        for r in rules.pairs() {
+            order.insert(r, Ordering::Less); // first then second
+            order.insert((r.1, r.0), Ordering::Greater);
+        }
+        order
+    }
+
+    /// The original check. As well as rejecting updates that break a rule, this rejects a page
+    /// with a rule about a later page once there's been a page with no rules about later pages,
+    /// which [check_order] doesn't, as no rule is broken. The two only differ when the rules
+    /// don't order every pair of pages in the update.
+    fn check_order_map(ordering: &HashMap<(usize, usize), Ordering>, vec: &[usize]) -> Option<usize> {
+        let mut is_unspecified = false;
+        for (first_idx, first_value) in vec.iter().enumerate() {
+            let mut inner_unspecified = true;
+            for second_value in vec.iter().skip(first_idx + 1) {
+                match ordering.get(&(*first_value, *second_value)) {
+                    Some(Ordering::Less) => { inner_unspecified = false; },
+                    Some(Ordering::Greater) => { return None }, // not valid, return immediately
+                    _ => {} // status quo
+                }
+            }
+            if is_unspecified && !inner_unspecified {
+                return None;
+            }
+            is_unspecified = inner_unspecified;
+        };
+        // If we get here, then the sequence is valid, so we find the middle.
+        let middle_idx = vec.len() / 2;
+        vec.get(middle_idx).copied()
+    }
+
+    /// The original sort, which is only right when the rules order every pair of pages.
+    fn sort_and_get_middle_map(ordering: &HashMap<(usize, usize), Ordering>, vec: &[usize]) -> usize {
+        let listed: HashSet<usize> = ordering.keys().map(|(first, _)| *first).collect();
+        let mut actual_vec = vec.iter().filter(|x| listed.contains(x)).collect::<Vec<&usize>>();
+        actual_vec.sort_by(|first, second| {
+            *ordering.get(&(**first, **second)).unwrap_or(&Ordering::Equal)
+        });
+        *actual_vec[vec.len() / 2]
+    }
+
+    /// Rules giving a random order over `pages` pages, and updates of odd length drawn from them,
+    /// shuffled about half the time. Each rule of the total order is kept with probability
+    /// `density`, so below 1 the rules only partly order the pages.
+    fn generate_input(pages: usize, updates: usize, density: f64, seed: u64) -> (Rules, Vec<Vec<usize>>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut order: Vec<usize> = (10..10 + pages).collect();
+        order.shuffle(&mut rng);
+        let mut rules = Rules::new();
+        for (idx, first) in order.iter().enumerate() {
+            for second in &order[idx + 1..] {
+                if rng.gen_bool(density) {
+                    rules.add(*first, *second).unwrap();
+                }
+            }
+        }
+
+        let updates = (0..updates).map(|_| {
+            let len = rng.gen_range(1..pages / 2) * 2 + 1;
+            let mut update: Vec<usize> = order.choose_multiple(&mut rng, len).copied().collect();
+            if rng.gen_bool(0.5) {
+                update.sort_by_key(|x| order.iter().position(|y| y == x));
+            }
+            update
+        }).collect();
+        (rules, updates)
+    }
+
+    #[test]
+    fn test_matches_map() {
+        for seed in 0..10 {
+            let (rules, updates) = generate_input(49, 200, 1.0, seed);
+            let ordering = parse_ordering_map(&rules);
+            for update in &updates {
+                assert_eq!(check_order(&rules, update), check_order_map(&ordering, update), "{:?}", update);
+                assert_eq!(sort_and_get_middle(&rules, update).unwrap(), sort_and_get_middle_map(&ordering, update), "{:?}", update);
+            }
+        }
+
+        // a partial order, where the fast path for sorting can't be used
+        let rules = Rules::from_pairs(&[(1, 2), (2, 3), (1, 4)]);
+        assert_eq!(sort_and_get_middle(&rules, &[4, 3, 2, 1, 5]).unwrap(), 2);
+        // every pair has a rule, but they make a cycle
+        let rules = Rules::from_pairs(&[(1, 2), (2, 3), (3, 1)]);
+        assert!(sort_and_get_middle(&rules, &[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_partial_orders() {
+        for seed in 0..10 {
+            let (rules, updates) = generate_input(20, 200, 0.3, seed);
+            let ordering = parse_ordering_map(&rules);
+            for update in &updates {
+                // valid exactly when no rule is broken, which the original check also needed
+                assert_eq!(check_order(&rules, update).is_some(), find_violations(&rules, update).is_empty(), "{:?}", update);
+                if check_order_map(&ordering, update).is_some() {
+                    assert!(check_order(&rules, update).is_some(), "{:?}", update);
+                }
+                // any order the sort gives breaks no rules
+                let middle = sort_and_get_middle(&rules, update).unwrap();
+                let (TopologicalOrder::Unique(sorted) | TopologicalOrder::Ambiguous(sorted)) = topological_sort(&rules, update) else { panic!("{:?}", update) };
+                assert!(find_violations(&rules, &sorted).is_empty(), "{:?}", update);
+                assert_eq!(middle, sorted[sorted.len() / 2]);
+            }
+        }
+
+        // 3 has no rules, so the original check wouldn't allow the rule about 1 after it, but no
+        // rule is broken
+        let rules = Rules::from_pairs(&[(1, 2)]);
+        assert_eq!(check_order(&rules, &[3, 1, 2]), Some(1));
+        assert_eq!(check_order_map(&parse_ordering_map(&rules), &[3, 1, 2]), None);
+    }
+
+    #[rstest]
+    #[case("1|100\n\n1,2,3")]
+    #[case("1|2\n\n1,2,128")]
+    #[case("1|2\n\n1,x,3")]
+    #[case("1|99999999999999999999999\n\n1,2,3")]
+    fn test_parse_bad_pages(#[case] input: &str) {
+        let mut lines = input.lines();
+        let result = parse_ordering(&mut lines).and_then(|_| parse_update(&mut lines));
+        assert!(matches!(result, Err(Errors::ParseFailed(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_add_bad_pages() {
+        let mut rules = Rules::new();
+        assert!(matches!(rules.add(1, PAGES), Err(Errors::ParseFailed(_))));
+        assert!(matches!(rules.add(128, 1), Err(Errors::ParseFailed(_))));
+        assert_eq!(rules, Rules::new());
+        assert!(rules.add(1, PAGES - 1).is_ok());
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_` to see the timings.
+    #[test]
+    #[ignore]
+    fn bench_rules() {
+        let (rules, updates) = generate_input(49, 20_000, 1.0, 1);
+        let ordering = parse_ordering_map(&rules);
+
+        let start = Instant::now();
+        let map_result: usize = updates.iter().map(|x| check_order_map(&ordering, x).unwrap_or_else(|| sort_and_get_middle_map(&ordering, x))).sum();
+        let map_time = start.elapsed();
+
+        let start = Instant::now();
+        let bitset_result: usize = updates.iter().map(|x| check_order(&rules, x).map_or_else(|| sort_and_get_middle(&rules, x), Ok)).sum::<Result<usize, _>>().unwrap();
+        let bitset_time = start.elapsed();
+
+        assert_eq!(map_result, bitset_result);
+        println!("{} updates: map {:?}, bitset {:?}", updates.len(), map_time, bitset_time);
+    }
+}
@@ -1,27 +1,96 @@
 use crate::util::{load_from, Errors};
 use crate::Day;
+use itertools::Itertools;
+use std::cell::OnceCell;
 use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io::{IsTerminal, Write};
 use std::str::Lines;
+use std::thread;
+use std::time::Duration;
 
-pub struct Day06 {}
+pub struct Day06 {
+    /// Shared by the details and the animation
+    walked: OnceCell<Walked>
+}
+
+/// The guard's walk, and the loops that obstacles on it cause.
+type Walked = (State<HashMap<Coord, Vec<Direction>>>, Vec<LoopReport>);
+
+impl Day06 {
+    /// Walks the guard and reports the loops, the first time either is needed.
+    fn walked(&self) -> Result<&Walked, Errors> {
+        if let Some(walked) = self.walked.get() {
+            return Ok(walked);
+        }
+        let data = load_from("day06a.txt")?;
+        let mut state = parse_grid(data.lines(), create_visited_map)?;
+        state.walk();
+        let threads = thread::available_parallelism().map_or(1, |x| x.get());
+        let reports = place_obstacles_and_walk(&state, threads);
+        Ok(self.walked.get_or_init(|| (state, reports)))
+    }
+}
 
 impl Day for Day06 {
     fn part_1(&self) -> Result<String, Errors> {
         let data = load_from("day06a.txt")?;
-        let mut state = parse_grid(data.lines(), create_visited_set);
+        let mut state = parse_grid(data.lines(), create_visited_set)?;
         state.walk();
         Ok(state.visited.len().to_string())
     }
 
     fn part_2(&self) -> Result<String, Errors> {
         let data = load_from("day06a.txt")?;
-        let mut state = parse_grid(data.lines(), create_visited_map);
+        let mut state = parse_grid(data.lines(), create_visited_map)?;
         state.walk();
-        Ok(place_obstacles_and_walk(&state).to_string())
+        let threads = thread::available_parallelism().map_or(1, |x| x.get());
+        Ok(loop_obstacles(&state, threads).len().to_string())
+    }
+
+    fn details(&self) -> Option<Result<String, Errors>> {
+        Some(self.walked().map(|(state, reports)| {
+            let exits = state.walk_all().iter().map(|(exit, _)| exit.to_string()).join("\n");
+            // how the guards fare under the other rules
+            let variants = [Turn::Right, Turn::Left, Turn::Reverse].iter()
+                .cartesian_product([Edges::Bounded, Edges::Wrap])
+                .map(|(turn, edges)| {
+                    let rules = GuardRules { turn: *turn, edges };
+                    let exits = state.clone().with_rules(rules).walk_all().iter().map(|(exit, _)| exit.to_string()).join(", ");
+                    format!("{:?} turns, {:?} edges: {}", turn, edges, exits)
+                })
+                .join("\n");
+            let style = if std::io::stdout().is_terminal() { Style::Ansi } else { Style::Ascii };
+            let loop_cells = state.loop_cells().unwrap_or_default();
+            let mut details = format!("{}\n{}\n{}", exits, variants, render(state, None, &loop_cells, style));
+
+            details.push_str(&reports.iter().join("\n"));
+            // draw the shortest loop, as the others can be most of the grid
+            if let Some(report) = reports.iter().min_by_key(|x| x.length) {
+                let mut looped = state.get_reset();
+                looped.obstacles.insert(report.obstacle);
+                looped.walk();
+                let loop_cells = looped.loop_cells().unwrap_or_default();
+                details.push_str(&format!("\n\n{}", render(&looped, Some(&report.obstacle), &loop_cells, style)));
+            }
+            details
+        }))
+    }
+
+    fn animate(&self) -> Option<Result<(), Errors>> {
+        Some(self.walked().and_then(|(state, reports)| {
+            // the shortest loop, as for the details, or the walk itself if nothing makes a loop
+            let added = reports.iter().min_by_key(|x| x.length).map(|x| x.obstacle);
+            // redirected output only gets the final frame, rather than every frame and the escape codes
+            let (style, delay) = if std::io::stdout().is_terminal() { (Style::Ansi, Some(Duration::from_millis(20))) } else { (Style::Ascii, None) };
+            animate(state, added.as_ref(), style, delay, &mut std::io::stdout().lock())
+                .map(|_| ())
+                .map_err(|e| Errors::BadResult(format!("Couldn't draw the walk: {}", e)))
+        }))
     }
 
     fn create_day() -> Box<dyn Day> where Self: Sized {
-        Box::new(Day06 {})
+        Box::new(Day06 { walked: OnceCell::new() })
     }
 }
 
@@ -31,29 +100,158 @@ type Coord = (isize, isize);
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 struct State<T> where T: Clone {
-    original_pos: Coord,
-    original_direction: Direction,
+    /// Where each guard starts, and which way they face
+    guards: Vec<(Coord, Direction)>,
+    /// The guard that's walking
+    guard: usize,
     position: Coord,
     direction: Direction,
+    /// How many squares the guard has moved
+    steps: usize,
     visited: T,
     obstacles: HashSet<Coord>,
     rows: usize,
-    cols: usize
+    cols: usize,
+    rules: GuardRules
 }
 
+/// Which way the guard turns at an obstacle.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Turn {
+    Left,
+    Right,
+    Reverse
+}
+
+/// What happens when the guard walks off the edge of the grid.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum Edges {
+    /// They leave
+    Bounded,
+    /// They come back in on the opposite side
+    Wrap
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+struct GuardRules {
+    turn: Turn,
+    edges: Edges
+}
+
+impl Default for GuardRules {
+    /// The puzzle's rules: turn right, and leave at the edge.
+    fn default() -> Self {
+        GuardRules { turn: Turn::Right, edges: Edges::Bounded }
+    }
+}
+
+/// How a guard's walk ended, and after how many squares.
 #[derive(Debug, PartialEq)]
 enum ExitCondition {
-    Loop,
-    Grid
+    /// The guard got back to a square they'd been in, facing the same way
+    Loop { guard: usize, steps: usize },
+    /// The guard left the grid
+    Grid { guard: usize, steps: usize }
+}
+
+impl ExitCondition {
+    fn is_loop(&self) -> bool {
+        matches!(self, ExitCondition::Loop { .. })
+    }
+}
+
+impl Display for ExitCondition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitCondition::Loop { guard, steps } => write!(f, "guard {} looped after {} steps", guard, steps),
+            ExitCondition::Grid { guard, steps } => write!(f, "guard {} left after {} steps", guard, steps)
+        }
+    }
 }
 
 trait Recorder {
     /// Records a visit, returning true if the visit has been seen before (position and direction)
     ///
-    /// If no direction is recorded, always returns false
+    /// If no direction is recorded, always returns false, leaving [State::walk] to stop a walk that
+    /// loops once it's made more moves than there are states
     fn record_visit(&mut self, position: &Coord, direction: &Direction) -> bool;
 
-    fn get_reset(&self) -> Self;
+    /// A copy with the given guard back at their start, and only their start visited.
+    fn reset_to(&self, guard: usize) -> Self;
+
+    fn get_reset(&self) -> Self where Self: Sized {
+        self.reset_to(0)
+    }
+}
+
+/// What happened when the guard took a step.
+#[derive(Debug, PartialEq)]
+enum Step {
+    Moved,
+    Turned,
+    Left
+}
+
+impl<T> State<T> where T: Clone {
+    /// Moves the guard one square forward, or turns them if there's an obstacle in the way,
+    /// without recording anything.
+    fn step(&mut self) -> Step {
+        let mut proposed = self.direction.step(&self.position);
+        // if we are in row or col -1, or beyond the last row or column (in row or col 130 in my input), we've left.
+        if proposed.0 < 0 || proposed.1 < 0 || proposed.0 >= self.rows as isize || proposed.1 >= self.cols as isize {
+            match self.rules.edges {
+                Edges::Bounded => return Step::Left,
+                Edges::Wrap => proposed = (proposed.0.rem_euclid(self.rows as isize), proposed.1.rem_euclid(self.cols as isize))
+            }
+        }
+        if self.obstacles.contains(&proposed) {
+            // discard proposed move and turn
+            self.direction = self.direction.turn(self.rules.turn);
+            Step::Turned
+        } else {
+            self.position = proposed;
+            self.steps += 1;
+            Step::Moved
+        }
+    }
+
+    fn with_rules(self, rules: GuardRules) -> State<T> {
+        State { rules, ..self }
+    }
+
+    /// The squares of the loop the guard is in, or None if they'll leave the grid.
+    ///
+    /// Only meaningful once [State::walk] has found a loop, as the guard must already be in it.
+    fn loop_cells(&self) -> Option<HashSet<Coord>> {
+        let mut state = self.clone_without_visits();
+        let mut cells = HashSet::from([state.position]);
+        // each square can be entered from each direction once per lap
+        for _ in 0..=4 * self.rows * self.cols {
+            if state.step() == Step::Left {
+                return None;
+            }
+            if state.position == self.position && state.direction == self.direction {
+                return Some(cells);
+            }
+            cells.insert(state.position);
+        }
+        None
+    }
+
+    fn clone_without_visits(&self) -> State<()> {
+        State {
+            guards: self.guards.clone(),
+            guard: self.guard,
+            position: self.position,
+            direction: self.direction.clone(),
+            steps: self.steps,
+            visited: (),
+            obstacles: self.obstacles.clone(),
+            rows: self.rows,
+            cols: self.cols,
+            rules: self.rules
+        }
+    }
 }
 
 impl<T> State<T> where State<T>: Recorder, T: Clone {
@@ -62,40 +260,48 @@ impl<T> State<T> where State<T>: Recorder, T: Clone {
     /// * a loop is detected (when the next move results in a location and position we've ended up in before), or
     /// * the guard moves out of the grid
     ///
+    /// Turns are recorded as visits too, in the direction the guard turned to. A recorder that
+    /// doesn't spot loops gets one reported once the guard has made a move from every square in
+    /// every direction, as they must have repeated themselves by then.
     pub fn walk(&mut self) -> ExitCondition {
-        loop {
-            let proposed = self.direction.step(&self.position);
-            // if we are in row or col -1, or beyond the last row or column (in row or col 130 in my input), we've left.
-            if proposed.0 >= 0 && proposed.1 >= 0 && proposed.0 < self.rows as isize && proposed.1 < self.cols as isize {
-                if self.obstacles.contains(&proposed) {
-                    // discard proposed move, rotate 90 deg to right
-                    self.direction = self.direction.rotate();
-                } else {
-                    // confirm move, insert step into tracking set
-                    self.position = proposed;
-                    let direction  = &self.direction.clone();
-                    if self.record_visit(&proposed, direction) {
-                        return ExitCondition::Loop;
+        for _ in 0..4 * self.rows * self.cols {
+            match self.step() {
+                Step::Left => return ExitCondition::Grid { guard: self.guard, steps: self.steps },
+                Step::Moved | Step::Turned => {
+                    let (position, direction) = (self.position, self.direction.clone());
+                    if self.record_visit(&position, &direction) {
+                        return ExitCondition::Loop { guard: self.guard, steps: self.steps };
                     }
                 }
-            } else {
-                return ExitCondition::Grid;
             }
         }
+        ExitCondition::Loop { guard: self.guard, steps: self.steps }
+    }
+
+    /// Walks each guard from their start in turn, returning how each walk ended and what that
+    /// guard visited. Guards don't block each other, so each walk is independent.
+    fn walk_all(&self) -> Vec<(ExitCondition, T)> {
+        (0..self.guards.len()).map(|guard| {
+            let mut state = self.reset_to(guard);
+            (state.walk(), state.visited)
+        }).collect()
     }
 }
 
 impl Recorder for State<HashSet<Coord>> {
     fn record_visit(&mut self, position: &Coord, _: &Direction) -> bool {
-        self.visited.insert(position.clone());
+        self.visited.insert(*position);
         false
     }
 
-    fn get_reset(&self) -> Self {
+    fn reset_to(&self, guard: usize) -> Self {
+        let (position, direction) = self.guards[guard].clone();
         State {
-            position: self.original_pos.clone(),
-            direction: self.original_direction.clone(),
-            visited: HashSet::from([self.original_pos.clone()]),
+            guard,
+            position,
+            direction,
+            steps: 0,
+            visited: HashSet::from([position]),
             ..self.clone()
         }
     }
@@ -111,39 +317,330 @@ impl Recorder for State<HashMap<Coord, Vec<Direction>>> {
                 false
             }
         } else {
-            self.visited.insert(position.clone(), vec![direction.clone()]);
+            self.visited.insert(*position, vec![direction.clone()]);
             false
         }
     }
 
-    fn get_reset(&self) -> Self {
+    fn reset_to(&self, guard: usize) -> Self {
+        let (position, direction) = self.guards[guard].clone();
         State {
-            position: self.original_pos.clone(),
-            direction: self.original_direction.clone(),
-            visited: HashMap::from([(self.original_pos.clone(), vec![self.original_direction.clone()])]),
+            guard,
+            position,
+            direction: direction.clone(),
+            steps: 0,
+            visited: HashMap::from([(position, vec![direction])]),
             ..self.clone()
         }
     }
 }
 
-fn place_obstacles_and_walk(original_state: &State<HashMap<Coord, Vec<Direction>>>) -> usize {
+/// An obstacle that makes the guard walk in a loop, and the loop they end up in.
+#[derive(Debug, PartialEq)]
+struct LoopReport {
+    obstacle: Coord,
+    /// How many squares the guard moves going round the loop once
+    length: usize,
+    /// The first square and direction the guard comes back to
+    repeated: (Coord, Direction),
+    /// How many squares the guard moves from their start before first reaching the repeated state
+    steps_before: usize
+}
+
+impl Display for LoopReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let ((row, col), direction) = &self.repeated;
+        write!(f, "obstacle at ({}, {}): {} steps in, loops every {} steps from ({}, {}) facing {}",
+               self.obstacle.0, self.obstacle.1, self.steps_before, self.length, row, col, direction.symbol())
+    }
+}
+
+/// Every obstacle that makes the guard walk in a loop, in order, checking the candidates across
+/// `threads` threads.
+fn place_obstacles_and_walk(original_state: &State<HashMap<Coord, Vec<Direction>>>, threads: usize) -> Vec<LoopReport> {
+    let obstacles = loop_obstacles(original_state, threads);
+    in_parallel(&obstacles, threads, |obstacles| {
+        obstacles.iter().map(|x| loop_report(original_state, x).unwrap()).collect()
+    })
+}
+
+/// Walks the guard from their start with the obstacle added, describing the loop they end up in, or
+/// None if they leave.
+fn loop_report<T: Clone>(state: &State<T>, obstacle: &Coord) -> Option<LoopReport> {
+    let mut walker = state.clone_without_visits();
+    (walker.position, walker.direction) = state.guards[state.guard].clone();
+    walker.steps = 0;
+    walker.obstacles.insert(*obstacle);
+    // the steps taken when each state was first reached
+    let mut seen: HashMap<(Coord, Direction), usize> = HashMap::from([((walker.position, walker.direction.clone()), 0)]);
+    loop {
+        if walker.step() == Step::Left {
+            return None;
+        }
+        if let Some(first) = seen.insert((walker.position, walker.direction.clone()), walker.steps) {
+            return Some(LoopReport {
+                obstacle: *obstacle,
+                length: walker.steps - first,
+                repeated: (walker.position, walker.direction),
+                steps_before: first
+            });
+        }
+    }
+}
+
+/// Every square where adding an obstacle makes the guard walk in a loop, in order, checking the
+/// candidates across `threads` threads.
+///
+/// This uses the path of whichever guard has walked. Under the puzzle's [GuardRules] the guard
+/// jumps from turn to turn, otherwise they walk each square.
+fn loop_obstacles(original_state: &State<HashMap<Coord, Vec<Direction>>>, threads: usize) -> Vec<Coord> {
     // If we're only placing ONE obstacle, then it has to be somewhere on the original path.
     // So, with our original path, we place an item on each square and see what happens.
-    // If we detect a loop, we count it.
     // We do not place an item on the first square
-    let mut count: usize = 0;
-    for (coord, direction) in original_state.visited.iter().filter(|(c, _)| **c != original_state.original_pos) {
-        // create the obstacle.
-        let mut new_state = original_state.get_reset();
-        new_state.obstacles.insert(coord.clone());
+    let candidates: Vec<(&Coord, &Direction)> = original_state.visited.iter()
+        .filter(|(c, _)| **c != original_state.guards[original_state.guard].0)
+        .map(|(coord, direction)| (coord, &direction[0]))
+        .collect();
+    let Some(jumps) = JumpTable::new(original_state) else {
+        let mut found = in_parallel(&candidates, threads, |candidates| {
+            candidates.iter()
+                .filter(|(coord, _)| loop_report(original_state, coord).is_some())
+                .map(|(coord, _)| **coord)
+                .collect()
+        });
+        found.sort();
+        return found;
+    };
+    let mut found = in_parallel(&candidates, threads, |candidates| {
+        let mut turns = TurnSet::new(&jumps);
         // We only walk from the point we first encounter this block.
-        new_state.position = direction[0].back_one(coord);
-        new_state.direction = direction[0].clone();
-        if new_state.walk() == ExitCondition::Loop {
-            count += 1;
+        candidates.iter()
+            .filter(|(coord, direction)| jumps.walk(original_state.guard, direction.back_one(coord), (*direction).clone(), coord, &mut turns).is_loop())
+            .map(|(coord, _)| **coord)
+            .collect()
+    });
+    found.sort();
+    found
+}
+
+/// Below this many items for each thread, starting the threads costs more than they save, as a
+/// candidate only takes a few microseconds to check.
+const MIN_ITEMS_PER_THREAD: usize = 1024;
+
+/// Splits the items into a chunk per thread, returning the results of each chunk in order. With
+/// too few items to be worth it, they're checked on this thread instead.
+fn in_parallel<I, O, F>(items: &[I], threads: usize, check: F) -> Vec<O> where I: Sync, O: Send, F: Fn(&[I]) -> Vec<O> + Sync {
+    let threads = threads.min(items.len() / MIN_ITEMS_PER_THREAD);
+    if threads <= 1 {
+        return check(items);
+    }
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        items.chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| check(chunk)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// The turns made on a walk, reused between walks so each only has to clear what it used.
+struct TurnSet {
+    turns: Vec<u8>,
+    used: Vec<usize>
+}
+
+impl TurnSet {
+    fn new(jumps: &JumpTable) -> TurnSet {
+        TurnSet { turns: vec![0; jumps.rows * jumps.cols], used: Vec::new() }
+    }
+
+    /// Records a turn, returning true if it's been made before.
+    fn insert(&mut self, index: usize, direction: &Direction) -> bool {
+        let turned = &mut self.turns[index];
+        if *turned & 1 << direction.index() != 0 {
+            return true;
+        }
+        if *turned == 0 {
+            self.used.push(index);
+        }
+        *turned |= 1 << direction.index();
+        false
+    }
+
+    fn clear(&mut self) {
+        self.used.drain(..).for_each(|x| self.turns[x] = 0);
+    }
+}
+
+/// For every square and direction, where the guard would stop if they walked that way: the square
+/// before the next obstacle, or None if they'd leave the grid first.
+///
+/// This lets the guard go straight from turn to turn, and as the walk only branches at turns, we
+/// only need to remember turns to spot a loop.
+struct JumpTable {
+    rows: usize,
+    cols: usize,
+    stops: Vec<[Option<Coord>; 4]>
+}
+
+impl JumpTable {
+    /// The jumps for the state's grid, or None if the guard isn't following the puzzle's rules, as
+    /// jumps only know how to turn right and stop at the edge.
+    fn new<T: Clone>(state: &State<T>) -> Option<JumpTable> {
+        if state.rules != GuardRules::default() {
+            return None;
+        }
+        let (rows, cols) = (state.rows as isize, state.cols as isize);
+        let mut stops = vec![[None; 4]; state.rows * state.cols];
+        let index = |coord: &Coord| (coord.0 * cols + coord.1) as usize;
+        let mut blocked = vec![false; state.rows * state.cols];
+        state.obstacles.iter().for_each(|x| blocked[index(x)] = true);
+        for direction in [Direction::North, Direction::East, Direction::South, Direction::West] {
+            // visit squares so the one ahead of each is done before it
+            let cells: Vec<Coord> = match direction {
+                Direction::North => (0..rows).flat_map(|row| (0..cols).map(move |col| (row, col))).collect(),
+                Direction::South => (0..rows).rev().flat_map(|row| (0..cols).map(move |col| (row, col))).collect(),
+                Direction::West => (0..cols).flat_map(|col| (0..rows).map(move |row| (row, col))).collect(),
+                Direction::East => (0..cols).rev().flat_map(|col| (0..rows).map(move |row| (row, col))).collect()
+            };
+            for cell in cells {
+                let ahead = direction.step(&cell);
+                stops[index(&cell)][direction.index()] = if ahead.0 < 0 || ahead.1 < 0 || ahead.0 >= rows || ahead.1 >= cols {
+                    None
+                } else if blocked[index(&ahead)] {
+                    Some(cell)
+                } else {
+                    stops[index(&ahead)][direction.index()]
+                };
+            }
+        }
+        Some(JumpTable { rows: state.rows, cols: state.cols, stops })
+    }
+
+    /// Where the guard stops walking from `position`, taking the `added` obstacle into account.
+    fn stop(&self, position: &Coord, direction: &Direction, added: &Coord) -> Option<Coord> {
+        let stop = self.stops[position.0 as usize * self.cols + position.1 as usize][direction.index()];
+        match direction.distance(position, added) {
+            Some(distance) if distance > 0 && stop.is_none_or(|x| distance <= direction.distance(position, &x).unwrap()) => Some(direction.back_one(added)),
+            _ => stop
+        }
+    }
+
+    /// Walks a guard from a position with the `added` obstacle in place, until they leave the grid
+    /// or come back to a turn they've made before. Steps are counted from that position.
+    fn walk(&self, guard: usize, mut position: Coord, mut direction: Direction, added: &Coord, turns: &mut TurnSet) -> ExitCondition {
+        turns.clear();
+        let mut steps = 0;
+        while let Some(stop) = self.stop(&position, &direction, added) {
+            steps += direction.distance(&position, &stop).unwrap() as usize;
+            if turns.insert(stop.0 as usize * self.cols + stop.1 as usize, &direction) {
+                return ExitCondition::Loop { guard, steps };
+            }
+            position = stop;
+            direction = direction.rotate();
+        }
+        let to_edge = match direction {
+            Direction::North => position.0,
+            Direction::East => self.cols as isize - 1 - position.1,
+            Direction::South => self.rows as isize - 1 - position.0,
+            Direction::West => position.1
+        };
+        ExitCondition::Grid { guard, steps: steps + to_edge as usize }
+    }
+}
+
+/// How [render] draws the grid.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Style {
+    /// Plain characters, as in the puzzle's own pictures
+    Ascii,
+    /// As [Style::Ascii], but with the loop in red and the added obstacle in yellow
+    Ansi
+}
+
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_RED: &str = "\x1b[31m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+const ANSI_BOLD: &str = "\x1b[1m";
+const ANSI_CLEAR: &str = "\x1b[H\x1b[2J";
+
+/// Draws the grid with the guard's walk so far: `#` for obstacles, `O` for the obstacle we added,
+/// `^`, `>`, `v` or `<` for where the guards started, and `|`, `-` or `+` for squares walked vertically,
+/// horizontally or both (which includes every square they turned in).
+///
+/// The `loop_cells` are highlighted in [Style::Ansi].
+fn render(state: &State<HashMap<Coord, Vec<Direction>>>, added: Option<&Coord>, loop_cells: &HashSet<Coord>, style: Style) -> String {
+    let mut output = String::with_capacity((state.cols + 1) * state.rows);
+    for row in 0..state.rows as isize {
+        for col in 0..state.cols as isize {
+            let coord = (row, col);
+            let c = if added == Some(&coord) {
+                'O'
+            } else if state.obstacles.contains(&coord) {
+                '#'
+            } else if let Some((_, direction)) = state.guards.iter().find(|(start, _)| *start == coord) {
+                direction.symbol()
+            } else {
+                match state.visited.get(&coord) {
+                    Some(directions) => {
+                        let vertical = directions.iter().any(|x| *x == Direction::North || *x == Direction::South);
+                        let horizontal = directions.iter().any(|x| *x == Direction::East || *x == Direction::West);
+                        match (vertical, horizontal) {
+                            (true, true) => '+',
+                            (true, false) => '|',
+                            _ => '-'
+                        }
+                    }
+                    None => '.'
+                }
+            };
+            match style {
+                Style::Ansi if c == 'O' => output.push_str(&format!("{}{}{}{}", ANSI_BOLD, ANSI_YELLOW, c, ANSI_RESET)),
+                Style::Ansi if loop_cells.contains(&coord) => output.push_str(&format!("{}{}{}", ANSI_RED, c, ANSI_RESET)),
+                _ => output.push(c)
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Walks the guard from the start, writing a frame to `out` after every move, clearing the
+/// terminal between them and waiting `delay` after each. With no delay, only the final frame is
+/// written, without clearing.
+///
+/// The guard starts back at their original position, with the `added` obstacle in place.
+fn animate<W: Write>(state: &State<HashMap<Coord, Vec<Direction>>>, added: Option<&Coord>, style: Style, delay: Option<Duration>, out: &mut W) -> std::io::Result<ExitCondition> {
+    let mut state = state.get_reset();
+    state.obstacles.extend(added);
+    // walk it through once to find the loop to highlight
+    let mut looped = state.clone();
+    looped.walk();
+    let loop_cells = looped.loop_cells().unwrap_or_default();
+    let exit = loop {
+        if let Some(delay) = delay {
+            write!(out, "{}{}", ANSI_CLEAR, render(&state, added, &loop_cells, style))?;
+            out.flush()?;
+            thread::sleep(delay);
+        }
+        match state.step() {
+            Step::Left => break ExitCondition::Grid { guard: state.guard, steps: state.steps },
+            Step::Moved | Step::Turned => {
+                let (position, direction) = (state.position, state.direction.clone());
+                if state.record_visit(&position, &direction) {
+                    break ExitCondition::Loop { guard: state.guard, steps: state.steps };
+                }
+            }
         }
+    };
+    if delay.is_some() {
+        write!(out, "{}", ANSI_CLEAR)?;
     }
-    count
+    write!(out, "{}", render(&state, added, &loop_cells, style))?;
+    out.flush()?;
+    Ok(exit)
 }
 
 
@@ -174,6 +671,54 @@ impl Direction {
         }
     }
 
+    fn index(&self) -> usize {
+        match self {
+            Direction::North => 0,
+            Direction::East => 1,
+            Direction::South => 2,
+            Direction::West => 3
+        }
+    }
+
+    /// How many steps it is to `to`, if it's straight ahead (or where we are).
+    fn distance(&self, from: &Coord, to: &Coord) -> Option<isize> {
+        let distance = match self {
+            Direction::North if from.1 == to.1 => from.0 - to.0,
+            Direction::East if from.0 == to.0 => to.1 - from.1,
+            Direction::South if from.1 == to.1 => to.0 - from.0,
+            Direction::West if from.0 == to.0 => from.1 - to.1,
+            _ => return None
+        };
+        (distance >= 0).then_some(distance)
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<'
+        }
+    }
+
+    fn from_symbol(symbol: char) -> Option<Direction> {
+        match symbol {
+            '^' => Some(Direction::North),
+            '>' => Some(Direction::East),
+            'v' => Some(Direction::South),
+            '<' => Some(Direction::West),
+            _ => None
+        }
+    }
+
+    fn turn(&self, turn: Turn) -> Direction {
+        match turn {
+            Turn::Left => self.rotate().rotate().rotate(),
+            Turn::Right => self.rotate(),
+            Turn::Reverse => self.rotate().rotate()
+        }
+    }
+
     fn rotate(&self) -> Direction {
         match self {
             Direction::North => Direction::East,
@@ -184,19 +729,20 @@ impl Direction {
     }
 }
 
-fn create_visited_set(coord: &Coord) -> HashSet<Coord> {
-    HashSet::from([coord.clone()])
+fn create_visited_set(coord: &Coord, _: &Direction) -> HashSet<Coord> {
+    HashSet::from([*coord])
 }
 
-fn create_visited_map(coord: &Coord) -> HashMap<Coord, Vec<Direction>> {
-    HashMap::from([(coord.clone(), vec![Direction::North])])
+fn create_visited_map(coord: &Coord, direction: &Direction) -> HashMap<Coord, Vec<Direction>> {
+    HashMap::from([(*coord, vec![direction.clone()])])
 }
 
-fn parse_grid<T, F>(lines: Lines, create: F) -> State<T> where F: Fn(&Coord) -> T, T: Clone {
+/// Reads the grid, with the first guard (in reading order) ready to walk under the puzzle's rules.
+fn parse_grid<T, F>(lines: Lines, create: F) -> Result<State<T>, Errors> where F: Fn(&Coord, &Direction) -> T, T: Clone {
     let mut rows: usize = 0;
     let mut cols: usize = 0;
     let mut obstacles: HashSet<Coord> = HashSet::new();
-    let mut position: Coord = (0, 0);
+    let mut guards: Vec<(Coord, Direction)> = Vec::new();
     for line in lines {
         if cols == 0 {
             cols = line.len();
@@ -204,18 +750,24 @@ fn parse_grid<T, F>(lines: Lines, create: F) -> State<T> where F: Fn(&Coord) ->
         line.match_indices('#').map(|(pos, _)| { pos }).for_each(|pos| {
             obstacles.insert((rows as isize, pos as isize));
         });
-        if let Some(idx) = line.find('^') {
-            position = (rows as isize, idx as isize);
-        }
+        line.char_indices()
+            .filter_map(|(idx, c)| Direction::from_symbol(c).map(|direction| ((rows as isize, idx as isize), direction)))
+            .for_each(|guard| guards.push(guard));
         rows += 1;
     }
-    let v = create(&position);
-    State { original_pos: position.clone(), original_direction: Direction::North, position, direction: Direction::North, obstacles, visited: v, rows, cols }
+    let (position, direction) = guards.first().cloned().ok_or_else(|| Errors::ParseFailed(String::from("No guard in the grid")))?;
+    let v = create(&position, &direction);
+    Ok(State { guards, guard: 0, position, direction, steps: 0, obstacles, visited: v, rows, cols, rules: GuardRules::default() })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::day06::{create_visited_map, create_visited_set, parse_grid, place_obstacles_and_walk, Coord, Direction, ExitCondition, State};
+    use crate::util::Errors;
+    use crate::day06::{animate, loop_report, LoopReport, create_visited_map, loop_obstacles, JumpTable, TurnSet, create_visited_set, in_parallel, parse_grid, place_obstacles_and_walk, render, Coord, Direction, Edges, ExitCondition, GuardRules, Recorder, State, Style, Turn, MIN_ITEMS_PER_THREAD};
+    use std::time::{Duration, Instant};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use lazy_static::lazy_static;
     use rstest::rstest;
     use std::collections::{HashMap, HashSet};
@@ -233,11 +785,12 @@ mod tests {
 
     lazy_static! {
         static ref TEST_STATE: State<HashSet<Coord>> = State {
+            guards: vec![((6, 4), Direction::North)],
+            guard: 0,
             position: (6, 4),
-            original_pos: (6, 4),
+            steps: 0,
             visited: HashSet::from([(6, 4)]),
             direction: Direction::North,
-            original_direction: Direction::North,
             obstacles: HashSet::from([
                 (0, 4),
                 (1, 9),
@@ -249,15 +802,17 @@ mod tests {
                 (9, 6)
             ]),
             rows: 10,
-            cols: 10
+            cols: 10,
+            rules: GuardRules::default()
         };
 
         static ref TEST_DIRECTION_STATE: State<HashMap<Coord, Vec<Direction>>> = State {
+            guards: vec![((6, 4), Direction::North)],
+            guard: 0,
             position: (6, 4),
-            original_pos: (6, 4),
+            steps: 0,
             visited: HashMap::from([((6, 4), vec![Direction::North])]),
             direction: Direction::North,
-            original_direction: Direction::North,
             obstacles: HashSet::from([
                 (0, 4),
                 (1, 9),
@@ -269,7 +824,8 @@ mod tests {
                 (9, 6)
             ]),
             rows: 10,
-            cols: 10
+            cols: 10,
+            rules: GuardRules::default()
         };
     }
 
@@ -306,18 +862,18 @@ mod tests {
 
     #[test]
     fn test_parse_grid_set() {
-        assert_eq!(parse_grid(TEST_GRID.lines(), create_visited_set), *TEST_STATE);
+        assert_eq!(parse_grid(TEST_GRID.lines(), create_visited_set).unwrap(), *TEST_STATE);
     }
 
     #[test]
     fn test_parse_grid_map() {
-        assert_eq!(parse_grid(TEST_GRID.lines(), create_visited_map), *TEST_DIRECTION_STATE);
+        assert_eq!(parse_grid(TEST_GRID.lines(), create_visited_map).unwrap(), *TEST_DIRECTION_STATE);
     }
 
     #[test]
     fn test_walk() {
         let mut state = (*TEST_STATE).clone();
-        assert_eq!(state.walk(), ExitCondition::Grid);
+        assert_eq!(state.walk(), ExitCondition::Grid { guard: 0, steps: 44 });
         assert_eq!(state.visited.len(), 41);
     }
 
@@ -325,7 +881,7 @@ mod tests {
     fn test_walk_map() {
         let mut state = (*TEST_DIRECTION_STATE).clone();
         state.walk();
-        assert_eq!(state.walk(), ExitCondition::Grid);
+        assert_eq!(state.walk(), ExitCondition::Grid { guard: 0, steps: 44 });
         assert_eq!(state.visited.len(), 41);
     }
 
@@ -333,10 +889,314 @@ mod tests {
     fn test_walk_new_obs() {
         let mut state = (*TEST_DIRECTION_STATE).clone();
         state.walk();
-        assert_eq!(state.walk(), ExitCondition::Grid);
+        assert_eq!(state.walk(), ExitCondition::Grid { guard: 0, steps: 44 });
         assert_eq!(state.visited.len(), 41);
 
-        assert_eq!(place_obstacles_and_walk(&state), 6)
+        assert_eq!(place_obstacles_and_walk(&state, 1).len(), 6);
+        assert_eq!(loop_obstacles(&state, 4), vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]);
+    }
+
+    const GUARDS_GRID: &str = ".#...\n\
+                               .^..<\n\
+                               .....\n\
+                               v....";
+
+    fn exits(rules: GuardRules) -> Vec<ExitCondition> {
+        parse_grid(GUARDS_GRID.lines(), create_visited_map).unwrap().with_rules(rules).walk_all().into_iter().map(|(exit, _)| exit).collect()
+    }
+
+    #[test]
+    fn test_parse_guards() {
+        let state = parse_grid(GUARDS_GRID.lines(), create_visited_map).unwrap();
+        assert_eq!(state.guards, vec![((1, 1), Direction::North), ((1, 4), Direction::West), ((3, 0), Direction::South)]);
+        assert_eq!((state.position, state.direction), ((1, 1), Direction::North));
+        assert_eq!(state.visited, HashMap::from([((1, 1), vec![Direction::North])]));
+    }
+
+    #[rstest]
+    #[case(Turn::Right, Edges::Bounded, vec![
+        ExitCondition::Grid { guard: 0, steps: 3 }, ExitCondition::Grid { guard: 1, steps: 4 }, ExitCondition::Grid { guard: 2, steps: 0 }
+    ])]
+    #[case(Turn::Left, Edges::Bounded, vec![
+        ExitCondition::Grid { guard: 0, steps: 1 }, ExitCondition::Grid { guard: 1, steps: 4 }, ExitCondition::Grid { guard: 2, steps: 0 }
+    ])]
+    #[case(Turn::Reverse, Edges::Bounded, vec![
+        ExitCondition::Grid { guard: 0, steps: 2 }, ExitCondition::Grid { guard: 1, steps: 4 }, ExitCondition::Grid { guard: 2, steps: 0 }
+    ])]
+    #[case(Turn::Right, Edges::Wrap, vec![
+        ExitCondition::Loop { guard: 0, steps: 5 }, ExitCondition::Loop { guard: 1, steps: 5 }, ExitCondition::Loop { guard: 2, steps: 4 }
+    ])]
+    fn test_walk_all(#[case] turn: Turn, #[case] edges: Edges, #[case] expected: Vec<ExitCondition>) {
+        assert_eq!(exits(GuardRules { turn, edges }), expected);
+    }
+
+    #[test]
+    fn test_walk_wrap_without_directions() {
+        // the set recorder can't spot loops, so the walk has to be cut short
+        let walks = parse_grid(GUARDS_GRID.lines(), create_visited_set).unwrap()
+            .with_rules(GuardRules { turn: Turn::Right, edges: Edges::Wrap })
+            .walk_all();
+        assert!(walks.iter().all(|(exit, _)| exit.is_loop()));
+        assert_eq!(walks[0].1, HashSet::from([(1, 1), (1, 2), (1, 3), (1, 4), (1, 0)]));
+    }
+
+    #[test]
+    fn test_parse_no_guard() {
+        assert!(matches!(parse_grid("..#\n...".lines(), create_visited_set), Err(Errors::ParseFailed(_))));
+    }
+
+    #[test]
+    fn test_walk_all_visits() {
+        let walks = parse_grid(GUARDS_GRID.lines(), create_visited_set).unwrap().walk_all();
+        assert_eq!(walks[0].1, HashSet::from([(1, 1), (1, 2), (1, 3), (1, 4)]));
+        assert_eq!(walks[2].1, HashSet::from([(3, 0)]));
+        assert_eq!(walks[1].0.to_string(), "guard 1 left after 4 steps");
+        assert_eq!(ExitCondition::Loop { guard: 2, steps: 7 }.to_string(), "guard 2 looped after 7 steps");
+    }
+
+    #[rstest]
+    #[case(Direction::North, Turn::Left, Direction::West)]
+    #[case(Direction::North, Turn::Right, Direction::East)]
+    #[case(Direction::North, Turn::Reverse, Direction::South)]
+    #[case(Direction::West, Turn::Left, Direction::South)]
+    #[case(Direction::East, Turn::Reverse, Direction::West)]
+    fn test_turn(#[case] original: Direction, #[case] turn: Turn, #[case] expected: Direction) {
+        assert_eq!(original.turn(turn), expected);
+    }
+
+    #[test]
+    fn test_loop_reports() {
+        let mut state = (*TEST_DIRECTION_STATE).clone();
+        state.walk();
+        let reports = place_obstacles_and_walk(&state, 2);
+        assert_eq!(reports.iter().map(|x| x.obstacle).collect::<Vec<Coord>>(), vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]);
+        assert_eq!(reports[0], LoopReport { obstacle: (6, 3), length: 18, repeated: ((6, 4), Direction::North), steps_before: 0 });
+        assert_eq!(reports[0].to_string(), "obstacle at (6, 3): 0 steps in, loops every 18 steps from (6, 4) facing ^");
+
+        for report in &reports {
+            // walking the steps before the loop, and then a lap, both end at the repeated state
+            let mut walker = state.get_reset();
+            walker.obstacles.insert(report.obstacle);
+            while walker.steps < report.steps_before || (walker.position, walker.direction.clone()) != report.repeated {
+                walker.step();
+            }
+            assert_eq!(walker.steps, report.steps_before, "{}", report);
+            walker.step();
+            while (walker.position, walker.direction.clone()) != report.repeated {
+                walker.step();
+            }
+            assert_eq!(walker.steps, report.steps_before + report.length, "{}", report);
+        }
+
+        assert_eq!(loop_report(&state, &(9, 9)), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut state = (*TEST_DIRECTION_STATE).clone();
+        state.walk();
+        assert_eq!(state.loop_cells(), None);
+        assert_eq!(
+            render(&state, None, &HashSet::new(), Style::Ascii),
+            "....#.....\n\
+             ....+---+#\n\
+             ....|...|.\n\
+             ..#.|...|.\n\
+             ..+-+-+#|.\n\
+             ..|.|.|.|.\n\
+             .#+-^-+-+.\n\
+             .+----++#.\n\
+             #+----+|..\n\
+             ......#|..\n"
+        );
+    }
+
+    #[test]
+    fn test_render_loop() {
+        let mut state = (*TEST_DIRECTION_STATE).get_reset();
+        state.obstacles.insert((6, 3));
+        assert_eq!(state.walk(), ExitCondition::Loop { guard: 0, steps: 18 });
+        let loop_cells = state.loop_cells().unwrap();
+        assert_eq!(
+            render(&state, Some(&(6, 3)), &loop_cells, Style::Ascii),
+            "....#.....\n\
+             ....+---+#\n\
+             ....|...|.\n\
+             ..#.|...|.\n\
+             ....|..#|.\n\
+             ....|...|.\n\
+             .#.O^---+.\n\
+             ........#.\n\
+             #.........\n\
+             ......#...\n"
+        );
+
+        let ansi = render(&state, Some(&(6, 3)), &loop_cells, Style::Ansi);
+        assert!(ansi.starts_with("....#.....\n....\x1b[31m+\x1b[0m\x1b[31m-\x1b[0m"));
+        assert!(ansi.contains(".#.\x1b[1m\x1b[33mO\x1b[0m\x1b[31m^\x1b[0m"));
+    }
+
+    #[test]
+    fn test_animate() {
+        let mut out: Vec<u8> = Vec::new();
+        let result = animate(&TEST_DIRECTION_STATE, Some(&(6, 3)), Style::Ascii, Some(Duration::ZERO), &mut out).unwrap();
+        assert_eq!(result, ExitCondition::Loop { guard: 0, steps: 18 });
+        let frames: Vec<String> = String::from_utf8(out).unwrap().split("\x1b[H\x1b[2J").skip(1).map(String::from).collect();
+        assert!(frames[0].contains(".#.O^....."));
+        assert!(frames[1].contains("....|.....\n.#.O^....."));
+        // the last frame has the whole loop
+        assert!(frames.last().unwrap().contains(".#.O^---+."));
+
+        let mut out: Vec<u8> = Vec::new();
+        animate(&TEST_DIRECTION_STATE, Some(&(6, 3)), Style::Ascii, None, &mut out).unwrap();
+        let mut state = (*TEST_DIRECTION_STATE).get_reset();
+        state.obstacles.insert((6, 3));
+        state.walk();
+        assert_eq!(String::from_utf8(out).unwrap(), render(&state, Some(&(6, 3)), &state.loop_cells().unwrap(), Style::Ascii));
+    }
+
+    /// The original cell by cell walk, kept as a reference for the jump table.
+    fn place_obstacles_and_walk_cell_by_cell(original_state: &State<HashMap<Coord, Vec<Direction>>>) -> usize {
+        let mut count: usize = 0;
+        for (coord, direction) in original_state.visited.iter().filter(|(c, _)| **c != original_state.guards[0].0) {
+            let mut new_state = original_state.get_reset();
+            new_state.obstacles.insert(*coord);
+            new_state.position = direction[0].back_one(coord);
+            new_state.direction = direction[0].clone();
+            if new_state.walk().is_loop() {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// A square grid with about `density` of it obstacles and the guard in the middle.
+    fn generate_grid(size: usize, density: f64, seed: u64) -> String {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..size).map(|row| {
+            (0..size).map(|col| {
+                if row == size / 2 && col == size / 2 {
+                    '^'
+                } else if rng.gen_bool(density) {
+                    '#'
+                } else {
+                    '.'
+                }
+            }).collect::<String>()
+        }).collect::<Vec<String>>().join("\n")
+    }
+
+    #[test]
+    fn test_jump_table() {
+        let jumps = JumpTable::new(&*TEST_STATE).unwrap();
+        assert_eq!(jumps.stop(&(6, 4), &Direction::North, &(-1, -1)), Some((1, 4)));
+        assert_eq!(jumps.stop(&(1, 4), &Direction::East, &(-1, -1)), Some((1, 8)));
+        assert_eq!(jumps.stop(&(1, 4), &Direction::West, &(-1, -1)), None);
+        // already facing an obstacle
+        assert_eq!(jumps.stop(&(1, 8), &Direction::East, &(-1, -1)), Some((1, 8)));
+        // the added obstacle is hit first, or is past the real one
+        assert_eq!(jumps.stop(&(6, 4), &Direction::North, &(3, 4)), Some((4, 4)));
+        assert_eq!(jumps.stop(&(6, 4), &Direction::North, &(5, 4)), Some((6, 4)));
+        assert_eq!(jumps.stop(&(1, 4), &Direction::East, &(1, 9)), Some((1, 8)));
+        assert_eq!(jumps.stop(&(6, 4), &Direction::West, &(6, 2)), Some((6, 3)));
+        assert_eq!(jumps.stop(&(1, 4), &Direction::West, &(1, 0)), Some((1, 1)));
+        // or isn't in the way at all
+        assert_eq!(jumps.stop(&(6, 4), &Direction::North, &(7, 4)), Some((1, 4)));
+
+        let mut turns = TurnSet::new(&jumps);
+        assert_eq!(jumps.walk(0, (6, 4), Direction::North, &(6, 3), &mut turns), ExitCondition::Loop { guard: 0, steps: 23 });
+        assert_eq!(jumps.walk(0, (6, 4), Direction::North, &(9, 9), &mut turns), ExitCondition::Grid { guard: 0, steps: 44 });
+        assert_eq!(jumps.walk(0, (6, 4), Direction::North, &(6, 3), &mut turns), ExitCondition::Loop { guard: 0, steps: 23 });
+    }
+
+    #[test]
+    fn test_jumps_match_cell_by_cell() {
+        for seed in 0..20 {
+            let grid = generate_grid(30, 0.08, seed);
+            let mut state = parse_grid(grid.lines(), create_visited_map).unwrap();
+            state.walk();
+            assert_eq!(loop_obstacles(&state, 1).len(), place_obstacles_and_walk_cell_by_cell(&state), "{}", grid);
+        }
+    }
+
+    #[test]
+    fn test_loop_obstacles_other_rules() {
+        let rules = [
+            GuardRules { turn: Turn::Left, edges: Edges::Bounded },
+            GuardRules { turn: Turn::Reverse, edges: Edges::Bounded },
+            GuardRules { turn: Turn::Right, edges: Edges::Wrap },
+            GuardRules { turn: Turn::Left, edges: Edges::Wrap },
+        ];
+        for seed in 0..10 {
+            let grid = generate_grid(20, 0.08, seed);
+            for rules in rules {
+                let mut state = parse_grid(grid.lines(), create_visited_map).unwrap().with_rules(rules);
+                state.walk();
+                assert!(JumpTable::new(&state).is_none());
+                assert_eq!(loop_obstacles(&state, 2).len(), place_obstacles_and_walk_cell_by_cell(&state), "{:?}\n{}", rules, grid);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_matches_serial() {
+        for seed in 0..10 {
+            let mut state = parse_grid(generate_grid(40, 0.06, seed).lines(), create_visited_map).unwrap();
+            state.walk();
+            let serial = loop_obstacles(&state, 1);
+            for threads in [2, 3, 8, 1000] {
+                assert_eq!(loop_obstacles(&state, threads), serial);
+            }
+        }
+    }
+
+    #[test]
+    fn test_in_parallel() {
+        let items: Vec<usize> = (0..10 * MIN_ITEMS_PER_THREAD).collect();
+        let serial: Vec<usize> = items.iter().map(|x| x * 2).collect();
+        for (threads, chunks) in [(1, 1), (2, 2), (3, 3), (1000, 10)] {
+            let calls = AtomicUsize::new(0);
+            let result = in_parallel(&items, threads, |chunk| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                chunk.iter().map(|x| x * 2).collect()
+            });
+            assert_eq!(result, serial);
+            assert_eq!(calls.into_inner(), chunks, "{} threads", threads);
+        }
+        // too few to be worth another thread
+        let calls = AtomicUsize::new(0);
+        in_parallel(&items[..MIN_ITEMS_PER_THREAD * 2 - 1], 8, |chunk| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            chunk.to_vec()
+        });
+        assert_eq!(calls.into_inner(), 1);
     }
 
-}
\ No newline at end of file
+    /// Run with `cargo test --release -- --ignored --nocapture bench_` to see the timings.
+    #[test]
+    #[ignore]
+    fn bench_place_obstacles() {
+        // random grids mostly send the guard straight out, so pick one with a long walk like the real input
+        let state = (0..200).map(|seed| {
+            let mut state = parse_grid(generate_grid(130, 0.04, seed).lines(), create_visited_map).unwrap();
+            state.walk();
+            state
+        }).max_by_key(|x| x.visited.len()).unwrap();
+
+        let start = Instant::now();
+        let cell_result = place_obstacles_and_walk_cell_by_cell(&state);
+        let cell_time = start.elapsed();
+
+        let start = Instant::now();
+        let jump_result = loop_obstacles(&state, 1).len();
+        let jump_time = start.elapsed();
+
+        let start = Instant::now();
+        let parallel_result = loop_obstacles(&state, 4).len();
+        let parallel_time = start.elapsed();
+
+        assert_eq!(cell_result, jump_result);
+        assert_eq!(cell_result, parallel_result);
+        println!("{} candidates, {} loops: cell by cell {:?}, jump table {:?}, 4 threads {:?}", state.visited.len(), jump_result, cell_time, jump_time, parallel_time);
+    }
+}
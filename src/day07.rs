@@ -2,8 +2,11 @@ use crate::util::{load_from, Errors};
 use crate::Day;
 use core::str::Lines;
 use itertools::Itertools;
-use std::collections::HashMap;
-use tailcall::tailcall;
+#[cfg(feature = "bigint")]
+use num_bigint::BigUint;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::Hash;
+use std::str::FromStr;
 
 pub struct Day07 {}
 
@@ -16,89 +19,594 @@ impl Day for Day07 {
         run(true)
     }
 
+    fn details(&self) -> Option<Result<String, Errors>> {
+        Some(load_from("day07a.txt").and_then(|string| {
+            let result = describe::<usize>(&string);
+            #[cfg(feature = "bigint")]
+            if let Err(Errors::Overflow(_)) = result {
+                return describe::<BigUint>(&string);
+            }
+            result
+        }))
+    }
+
     fn create_day() -> Box<dyn Day> where Self: Sized {
         Box::new(Day07 {})
     }
 }
 
-fn run(part2: bool) -> Result<String, Errors> {
+/// Solves with `usize`, falling back to arbitrary precision if something doesn't fit and the
+/// `bigint` feature is on.
+fn run(concatenate: bool) -> Result<String, Errors> {
     let string = load_from("day07a.txt")?;
-    let value_map = parse_lines(string.lines());
-    let result: usize = value_map.iter()
-        .map(|(target, values)| operate(vec![], values, *target, part2))
-        .sum();
-    Ok(format!("{}", result))
-}
-
-#[tailcall]
-fn operate(current_state: Vec<usize>, values: &[usize], expected: usize, with_concatenation: bool) -> usize {
-    if values.is_empty() {
-        if current_state.iter().any(|node| *node == expected) {
-            // we need to sum on this node
-            expected
-        } else {
-            // not valid, don't add to sum
-            0
+    let result = calibrate::<usize>(&string, concatenate);
+    #[cfg(feature = "bigint")]
+    if let Err(Errors::Overflow(_)) = result {
+        return calibrate::<BigUint>(&string, concatenate);
+    }
+    result
+}
+
+/// A solution for each line, if it has any, and how many it has.
+fn describe<N: Number>(input: &str) -> Result<String, Errors> {
+    let operators = add_multiply_concatenate::<N>();
+    let lines = parse_lines::<N>(input.lines())?.iter()
+        .map(|Equation { line, target, values }| {
+            let found = solutions(values, target, &operators, usize::MAX)?;
+            Ok(match found.first() {
+                Some(first) => format!("line {}: {} = {} ({} solution{})", line, target, first, found.len(), if found.len() == 1 { "" } else { "s" }),
+                None => format!("line {}: {} = {} has no solutions", line, target, values.iter().join(" "))
+            })
+        })
+        .collect::<Result<Vec<String>, Errors>>()?;
+    Ok(lines.join("\n"))
+}
+
+fn calibrate<N: Number>(input: &str, concatenate: bool) -> Result<String, Errors> {
+    let operators = if concatenate { add_multiply_concatenate() } else { add_multiply() };
+    let total: N = calibration_total(&parse_lines(input.lines())?, &operators)?;
+    Ok(format!("{}", total))
+}
+
+/// The numbers the solver works with: `usize`, which reports overflow, or (with the `bigint`
+/// feature) `BigUint`, which doesn't overflow.
+trait Number: Clone + Eq + Hash + PartialOrd + Debug + Display + FromStr + Send + Sync + 'static {
+    fn from_usize(value: usize) -> Self;
+
+    fn is_zero(&self) -> bool;
+
+    /// None if the result doesn't fit.
+    fn checked_add(&self, rhs: &Self) -> Option<Self>;
+
+    /// None if the result would be negative.
+    fn checked_sub(&self, rhs: &Self) -> Option<Self>;
+
+    /// None if the result doesn't fit.
+    fn checked_mul(&self, rhs: &Self) -> Option<Self>;
+
+    /// None if the result doesn't fit.
+    fn checked_pow(&self, exponent: u32) -> Option<Self>;
+
+    /// The quotient and remainder, with `rhs` not zero.
+    fn div_rem(&self, rhs: &Self) -> (Self, Self);
+
+    /// The largest value whose `n`th power is no more than this, for `n` above zero.
+    #[cfg(test)]
+    fn nth_root(&self, n: u32) -> Self;
+
+    /// How many digits this has when written in `base`.
+    fn digits(&self, base: usize) -> u32;
+
+    #[cfg(test)]
+    fn to_u32(&self) -> Option<u32>;
+}
+
+impl Number for usize {
+    fn from_usize(value: usize) -> Self {
+        value
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        usize::checked_add(*self, *rhs)
+    }
+
+    fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        usize::checked_sub(*self, *rhs)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        usize::checked_mul(*self, *rhs)
+    }
+
+    fn checked_pow(&self, exponent: u32) -> Option<Self> {
+        usize::checked_pow(*self, exponent)
+    }
+
+    fn div_rem(&self, rhs: &Self) -> (Self, Self) {
+        (self / rhs, self % rhs)
+    }
+
+    #[cfg(test)]
+    fn nth_root(&self, n: u32) -> Self {
+        if n == 1 {
+            return *self;
         }
-    } else {
-        let next = values[0];
-        let next_state: Vec<usize> = if current_state.is_empty() {
-            vec![next]
+        // a binary search, as a float estimate is too rough for big values
+        let (mut low, mut high) = (0, 1usize << (usize::BITS / n + 1));
+        while low < high {
+            let middle = low + (high - low).div_ceil(2);
+            if middle.checked_pow(n).is_some_and(|x| x <= *self) {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        low
+    }
+
+    fn digits(&self, base: usize) -> u32 {
+        self.checked_ilog(base).unwrap_or(0) + 1
+    }
+
+    #[cfg(test)]
+    fn to_u32(&self) -> Option<u32> {
+        u32::try_from(*self).ok()
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Number for BigUint {
+    fn from_usize(value: usize) -> Self {
+        BigUint::from(value)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.bits() == 0
+    }
+
+    fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+
+    fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        (self >= rhs).then(|| self - rhs)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+
+    fn checked_pow(&self, exponent: u32) -> Option<Self> {
+        Some(self.pow(exponent))
+    }
+
+    fn div_rem(&self, rhs: &Self) -> (Self, Self) {
+        (self / rhs, self % rhs)
+    }
+
+    #[cfg(test)]
+    fn nth_root(&self, n: u32) -> Self {
+        BigUint::nth_root(self, n)
+    }
+
+    fn digits(&self, base: usize) -> u32 {
+        self.to_radix_be(base as u32).len() as u32
+    }
+
+    #[cfg(test)]
+    fn to_u32(&self) -> Option<u32> {
+        u32::try_from(self).ok()
+    }
+}
+
+fn overflow<N: Number>(lhs: &N, symbol: &str, rhs: &N) -> Errors {
+    Errors::Overflow(format!("{} {} {} doesn't fit", lhs, symbol, rhs))
+}
+
+/// A calibration equation, and the line (from 1) it was on.
+#[derive(Debug, PartialEq)]
+struct Equation<N = usize> {
+    line: usize,
+    target: N,
+    values: Vec<N>
+}
+
+/// The sum of the targets of the equations that can be solved.
+fn calibration_total<N: Number>(equations: &[Equation<N>], operators: &[&dyn Operator<N>]) -> Result<N, Errors> {
+    equations.iter().try_fold(N::from_usize(0), |total, x| {
+        if solvable(&x.values, &x.target, operators)? {
+            total.checked_add(&x.target).ok_or_else(|| overflow(&total, "+", &x.target))
         } else {
-            current_state.iter().flat_map(|val| {
-                [
-                    evaluate_valid(val + next, expected),
-                    evaluate_valid(val * next, expected),
-                    if with_concatenation { evaluate_valid(concat_digits(*val, next), expected) } else { None }
-                ]
-                    .iter()
-                    .filter_map(|x| *x)
-                    .collect_vec()
-            }).collect_vec()
+            Ok(total)
+        }
+    })
+}
+
+/// The values an operator's left hand side could have been, given its result and right hand side.
+#[derive(Debug, PartialEq)]
+enum Inverse<N = usize> {
+    /// Nothing gives that result
+    None,
+    One(N),
+    /// Every value gives that result, like multiplying by zero
+    Any
+}
+
+/// Combines the running total (on the left) with the next value (on the right).
+///
+/// Errors are for results that don't fit in `N`, rather than those that are undefined.
+trait Operator<N = usize>: Sync {
+    fn symbol(&self) -> &str;
+
+    /// The result, or None if it's undefined.
+    fn apply(&self, lhs: &N, rhs: &N) -> Result<Option<N>, Errors>;
+
+    fn inverse(&self, result: &N, rhs: &N) -> Result<Inverse<N>, Errors>;
+}
+
+struct Add;
+struct Multiply;
+struct Concatenate {
+    base: usize
+}
+/// Only used by the tests, to check the search copes with operators that can shrink the total.
+#[cfg(test)]
+struct Subtract;
+#[cfg(test)]
+struct Power;
+
+fn add_multiply<N: Number>() -> Vec<&'static dyn Operator<N>> {
+    vec![&Add, &Multiply]
+}
+
+fn add_multiply_concatenate<N: Number>() -> Vec<&'static dyn Operator<N>> {
+    vec![&Add, &Multiply, &Concatenate { base: 10 }]
+}
+
+impl<N: Number> Operator<N> for Add {
+    fn symbol(&self) -> &str {
+        "+"
+    }
+
+    fn apply(&self, lhs: &N, rhs: &N) -> Result<Option<N>, Errors> {
+        lhs.checked_add(rhs).map(Some).ok_or_else(|| overflow(lhs, "+", rhs))
+    }
+
+    fn inverse(&self, result: &N, rhs: &N) -> Result<Inverse<N>, Errors> {
+        Ok(result.checked_sub(rhs).map_or(Inverse::None, Inverse::One))
+    }
+}
+
+impl<N: Number> Operator<N> for Multiply {
+    fn symbol(&self) -> &str {
+        "*"
+    }
+
+    fn apply(&self, lhs: &N, rhs: &N) -> Result<Option<N>, Errors> {
+        lhs.checked_mul(rhs).map(Some).ok_or_else(|| overflow(lhs, "*", rhs))
+    }
+
+    fn inverse(&self, result: &N, rhs: &N) -> Result<Inverse<N>, Errors> {
+        if rhs.is_zero() {
+            return Ok(if result.is_zero() { Inverse::Any } else { Inverse::None });
+        }
+        let (quotient, remainder) = result.div_rem(rhs);
+        Ok(if remainder.is_zero() { Inverse::One(quotient) } else { Inverse::None })
+    }
+}
+
+impl<N: Number> Operator<N> for Concatenate {
+    fn symbol(&self) -> &str {
+        "||"
+    }
+
+    fn apply(&self, lhs: &N, rhs: &N) -> Result<Option<N>, Errors> {
+        concat_digits(lhs, rhs, self.base).map(Some).ok_or_else(|| overflow(lhs, "||", rhs))
+    }
+
+    fn inverse(&self, result: &N, rhs: &N) -> Result<Inverse<N>, Errors> {
+        Ok(match N::from_usize(self.base).checked_pow(rhs.digits(self.base)) {
+            Some(shift) => {
+                let (quotient, remainder) = result.div_rem(&shift);
+                if remainder == *rhs { Inverse::One(quotient) } else { Inverse::None }
+            }
+            // rhs has more digits than fit, so only concatenating to 0 gives it
+            None if result == rhs => Inverse::One(N::from_usize(0)),
+            None => Inverse::None
+        })
+    }
+}
+
+#[cfg(test)]
+impl<N: Number> Operator<N> for Subtract {
+    fn symbol(&self) -> &str {
+        "-"
+    }
+
+    fn apply(&self, lhs: &N, rhs: &N) -> Result<Option<N>, Errors> {
+        Ok(lhs.checked_sub(rhs))
+    }
+
+    fn inverse(&self, result: &N, rhs: &N) -> Result<Inverse<N>, Errors> {
+        result.checked_add(rhs).map(Inverse::One).ok_or_else(|| overflow(result, "+", rhs))
+    }
+}
+
+#[cfg(test)]
+impl<N: Number> Operator<N> for Power {
+    fn symbol(&self) -> &str {
+        "^"
+    }
+
+    fn apply(&self, lhs: &N, rhs: &N) -> Result<Option<N>, Errors> {
+        rhs.to_u32()
+            .and_then(|exponent| lhs.checked_pow(exponent))
+            .map(Some)
+            .ok_or_else(|| overflow(lhs, "^", rhs))
+    }
+
+    fn inverse(&self, result: &N, rhs: &N) -> Result<Inverse<N>, Errors> {
+        let one = N::from_usize(1);
+        Ok(match rhs.to_u32() {
+            Some(0) if *result == one => Inverse::Any,
+            Some(0) => Inverse::None,
+            Some(exponent) => {
+                let root = result.nth_root(exponent);
+                if root.checked_pow(exponent).as_ref() == Some(result) { Inverse::One(root) } else { Inverse::None }
+            }
+            // only 0 and 1 stay small to such a big power
+            None if result.is_zero() || *result == one => Inverse::One(result.clone()),
+            None => Inverse::None
+        })
+    }
+}
+
+/// Whether some choice of operators between the values gives the target.
+///
+/// This works back from the target: the last operator was applied to the last value, so each
+/// operator that can be undone (dividing for `*`, removing the suffix for `||`, subtracting without
+/// going below zero for `+`) gives a smaller target for the rest of the values. As in [evaluates],
+/// branches that overflow are dropped, so this is only an error if no other branch works.
+fn solvable<N: Number>(values: &[N], target: &N, operators: &[&dyn Operator<N>]) -> Result<bool, Errors> {
+    match values.split_last() {
+        None => Ok(false),
+        Some((first, [])) => Ok(first == target),
+        Some((last, rest)) => {
+            let mut overflow = None;
+            for operator in operators {
+                let found = operator.inverse(target, last).and_then(|inverse| match inverse {
+                    Inverse::None => Ok(false),
+                    Inverse::One(lhs) => solvable(rest, &lhs, operators),
+                    Inverse::Any => evaluates(rest, operators)
+                });
+                match found {
+                    Ok(true) => return Ok(true),
+                    Ok(false) => {},
+                    Err(e) => overflow = Some(e)
+                }
+            }
+            overflow.map_or(Ok(false), Err)
+        }
+    }
+}
+
+/// Whether any choice of operators between the values gives a result at all.
+///
+/// Choices that overflow along the way are dropped, so this is only an error if nothing else gives
+/// a result either.
+fn evaluates<N: Number>(values: &[N], operators: &[&dyn Operator<N>]) -> Result<bool, Errors> {
+    let mut overflow = None;
+    let mut apply = |operator: &&dyn Operator<N>, lhs: &N, rhs: &N| match operator.apply(lhs, rhs) {
+        Ok(result) => result,
+        Err(e) => {
+            overflow = Some(e);
+            None
+        }
+    };
+    let found = match values.split_last() {
+        None => false,
+        Some((_, [])) => true,
+        Some((last, rest)) => {
+            // the values each choice of operators for the rest can give, working forwards
+            let mut totals = vec![rest[0].clone()];
+            for value in &rest[1..] {
+                totals = totals.iter().flat_map(|x| operators.iter().filter_map(|operator| apply(operator, x, value)).collect_vec()).unique().collect();
+            }
+            totals.iter().any(|x| operators.iter().any(|operator| apply(operator, x, last).is_some()))
+        }
+    };
+    match overflow {
+        Some(e) if !found => Err(e),
+        _ => Ok(found)
+    }
+}
+
+/// Values with an operator between each pair, evaluated left to right.
+#[derive(Clone)]
+struct Expression<'a, N = usize> {
+    values: &'a [N],
+    operators: Vec<&'a dyn Operator<N>>
+}
+
+impl<N: Number> Expression<'_, N> {
+    fn evaluate(&self) -> Result<Option<N>, Errors> {
+        let Some((first, rest)) = self.values.split_first() else {
+            return Ok(None);
         };
-        operate(next_state, values.split_first().map(|x| x.1).unwrap_or(&[]), expected, with_concatenation)
+        let mut total = first.clone();
+        for (value, operator) in rest.iter().zip(&self.operators) {
+            match operator.apply(&total, value)? {
+                Some(next) => total = next,
+                None => return Ok(None)
+            }
+        }
+        Ok(Some(total))
     }
 }
 
-fn concat_digits(lhs: usize, rhs: usize) -> usize {
-    let left_shift = get_digits(rhs);
-    lhs * 10_usize.pow(left_shift) + rhs
+impl<N: Number> Display for Expression<'_, N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (first, rest) = self.values.split_first().ok_or(std::fmt::Error)?;
+        write!(f, "{}", first)?;
+        rest.iter().zip(&self.operators).try_for_each(|(value, operator)| write!(f, " {} {}", operator.symbol(), value))
+    }
 }
 
-fn get_digits(value: usize) -> u32 {
-    value.checked_ilog10().unwrap_or(0) + 1
+/// Up to `limit` choices of operators that make the values give the target, searching backwards
+/// like [solvable].
+fn solutions<'a, N: Number>(values: &'a [N], target: &N, operators: &[&'a dyn Operator<N>], limit: usize) -> Result<Vec<Expression<'a, N>>, Errors> {
+    let mut found = Vec::new();
+    find_solutions(values, target, operators, &mut Vec::new(), &mut found, limit)?;
+    Ok(found.into_iter().map(|operators| Expression { values, operators }).collect())
 }
 
-fn evaluate_valid(result: usize, expected: usize) -> Option<usize> {
-    if result > expected {
-        None
-    } else {
-        Some(result)
+/// Adds to `found` each choice of operators for `values` that gives the target, each followed by
+/// the `chosen` operators for the values after them. As in [solvable], branches that overflow are
+/// dropped, so this is only an error if none of them add anything.
+fn find_solutions<'a, N: Number>(values: &[N], target: &N, operators: &[&'a dyn Operator<N>], chosen: &mut Vec<&'a dyn Operator<N>>, found: &mut Vec<Vec<&'a dyn Operator<N>>>, limit: usize) -> Result<(), Errors> {
+    let (before, mut overflow) = (found.len(), None);
+    match values.split_last() {
+        Some((first, [])) if first == target && found.len() < limit => found.push(chosen.iter().rev().copied().collect()),
+        None | Some((_, [])) => {},
+        Some((last, rest)) => {
+            for operator in operators {
+                if found.len() >= limit {
+                    break;
+                }
+                chosen.push(*operator);
+                match operator.inverse(target, last) {
+                    Ok(Inverse::None) => {},
+                    Ok(Inverse::One(lhs)) => {
+                        if let Err(e) = find_solutions(rest, &lhs, operators, chosen, found, limit) {
+                            overflow = Some(e);
+                        }
+                    }
+                    Ok(Inverse::Any) => {
+                        let suffix: Vec<&dyn Operator<N>> = chosen.iter().rev().copied().collect();
+                        for mut choice in all_choices(rest, operators) {
+                            if found.len() >= limit {
+                                break;
+                            }
+                            match (Expression { values: rest, operators: choice.clone() }).evaluate() {
+                                Ok(Some(_)) => {
+                                    choice.extend(&suffix);
+                                    found.push(choice);
+                                }
+                                Ok(None) => {},
+                                Err(e) => overflow = Some(e)
+                            }
+                        }
+                    }
+                    Err(e) => overflow = Some(e)
+                }
+                chosen.pop();
+            }
+        }
+    }
+    match overflow {
+        Some(e) if found.len() == before => Err(e),
+        _ => Ok(())
     }
 }
 
-fn parse_lines(lines: Lines) -> HashMap<usize, Vec<usize>> {
-    lines.map(parse_line).collect()
+/// Every choice of operators between the values.
+fn all_choices<'a, N>(values: &[N], operators: &[&'a dyn Operator<N>]) -> Vec<Vec<&'a dyn Operator<N>>> {
+    if values.len() <= 1 {
+        return vec![vec![]];
+    }
+    (1..values.len()).map(|_| operators.iter().copied()).multi_cartesian_product().collect()
 }
 
-fn parse_line(line: &str) -> (usize, Vec<usize>) {
-    line.split_once(": ")
-        .map(|(target, numbers)| {
-            (
-                target.parse::<usize>().unwrap(),
-                numbers.trim().split(' ').map(|x| x.parse::<usize>().unwrap()).collect()
-            )
-        }).unwrap()
+/// `lhs` followed by the digits of `rhs` in `base`, or None if that doesn't fit.
+fn concat_digits<N: Number>(lhs: &N, rhs: &N, base: usize) -> Option<N> {
+    let left_shift = rhs.digits(base);
+    lhs.checked_mul(&N::from_usize(base).checked_pow(left_shift)?)?.checked_add(rhs)
+}
+
+fn parse_lines<N: Number>(lines: Lines) -> Result<Vec<Equation<N>>, Errors> {
+    lines.enumerate()
+        .map(|(idx, line)| {
+            let (target, values) = parse_line(line)?;
+            Ok(Equation { line: idx + 1, target, values })
+        })
+        .collect()
+}
+
+fn parse_line<N: Number>(line: &str) -> Result<(N, Vec<N>), Errors> {
+    let (target, numbers) = line.split_once(": ").ok_or_else(|| Errors::ParseFailed(format!("No target in {}", line)))?;
+    Ok((parse_number(target)?, numbers.trim().split(' ').map(parse_number).collect::<Result<Vec<N>, Errors>>()?))
+}
+
+fn parse_number<N: Number>(number: &str) -> Result<N, Errors> {
+    number.parse::<N>().map_err(|_| {
+        if !number.is_empty() && number.bytes().all(|x| x.is_ascii_digit()) {
+            Errors::Overflow(format!("{} doesn't fit", number))
+        } else {
+            Errors::ParseFailed(format!("{} isn't a number", number))
+        }
+    })
 }
 
 
 
 #[cfg(test)]
 mod tests {
-    use crate::day07::{operate, parse_line, parse_lines, concat_digits};
+    use crate::day07::{parse_line, solvable, solutions, evaluates, Expression, parse_lines, calibration_total, calibrate, Equation, concat_digits, Add, Concatenate, Inverse, Multiply, Operator, Power, Subtract};
+    use crate::util::Errors;
+    use itertools::Itertools;
     use lazy_static::lazy_static;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
     use rstest::rstest;
-    use std::collections::HashMap;
+    use std::time::Instant;
+    use tailcall::tailcall;
+
+    const ADD_MULTIPLY: &[&dyn Operator] = &[&Add, &Multiply];
+    const ADD_MULTIPLY_CONCATENATE: &[&dyn Operator] = &[&Add, &Multiply, &Concatenate { base: 10 }];
+
+    /// Whether the result is never less than the left hand side for this right hand side, so a
+    /// running total that's already past the target can be dropped.
+    fn increasing(operator: &dyn Operator, rhs: usize) -> bool {
+        match operator.symbol() {
+            "-" => rhs == 0,
+            // anything times 0 is 0, and anything to the power 0 is 1
+            "*" | "^" => rhs != 0,
+            _ => true
+        }
+    }
+
+    /// The original forward search, kept as a reference for [solvable]: every running total for
+    /// the values so far, dropping those past the target when nothing after can make them smaller.
+    #[tailcall]
+    fn operate(current_state: Vec<usize>, values: &[usize], expected: usize, operators: &[&dyn Operator]) -> usize {
+        if values.is_empty() {
+            if current_state.contains(&expected) {
+                // we need to sum on this node
+                expected
+            } else {
+                // not valid, don't add to sum
+                0
+            }
+        } else {
+            let next = values[0];
+            let prune = values[1..].iter().all(|value| operators.iter().all(|x| increasing(*x, *value)));
+            let next_state: Vec<usize> = if current_state.is_empty() {
+                vec![next]
+            } else {
+                current_state.iter().flat_map(|val| {
+                    operators.iter()
+                        .filter_map(|operator| operator.apply(val, &next).ok().flatten())
+                        .filter(|x| !prune || *x <= expected)
+                        .collect_vec()
+                }).collect_vec()
+            };
+            operate(next_state, values.split_first().map(|x| x.1).unwrap_or(&[]), expected, operators)
+        }
+    }
 
     const TEST_INPUT: &str = "190: 10 19\n\
                               3267: 81 40 27\n\
@@ -111,24 +619,40 @@ mod tests {
                               292: 11 6 16 20";
 
     lazy_static! {
-        static ref TEST_RESULT: HashMap<usize, Vec<usize>> = HashMap::from(
-            [
-                (190, vec![10, 19]),
-                (3267, vec![81, 40, 27]),
-                (83, vec![17, 5]),
-                (156, vec![15, 6]),
-                (7290, vec![6, 8, 6, 15]),
-                (161011, vec![16, 10, 13]),
-                (192, vec![17, 8, 14]),
-                (21037, vec![9, 7, 18, 13]),
-                (292, vec![11, 6, 16, 20]),
-            ]
-        );
+        static ref TEST_RESULT: Vec<Equation> = vec![
+            Equation { line: 1, target: 190, values: vec![10, 19] },
+            Equation { line: 2, target: 3267, values: vec![81, 40, 27] },
+            Equation { line: 3, target: 83, values: vec![17, 5] },
+            Equation { line: 4, target: 156, values: vec![15, 6] },
+            Equation { line: 5, target: 7290, values: vec![6, 8, 6, 15] },
+            Equation { line: 6, target: 161011, values: vec![16, 10, 13] },
+            Equation { line: 7, target: 192, values: vec![17, 8, 14] },
+            Equation { line: 8, target: 21037, values: vec![9, 7, 18, 13] },
+            Equation { line: 9, target: 292, values: vec![11, 6, 16, 20] },
+        ];
     }
 
     #[test]
     fn test_parse_lines() {
-        assert_eq!(parse_lines(TEST_INPUT.lines()), *TEST_RESULT);
+        assert_eq!(parse_lines(TEST_INPUT.lines()).unwrap(), *TEST_RESULT);
+    }
+
+    #[test]
+    fn test_calibration_total() {
+        assert_eq!(calibration_total(&TEST_RESULT, ADD_MULTIPLY).unwrap(), 3749);
+        assert_eq!(calibration_total(&TEST_RESULT, ADD_MULTIPLY_CONCATENATE).unwrap(), 11387);
+    }
+
+    #[test]
+    fn test_duplicate_targets() {
+        // keyed by target, the second and fourth lines replaced the first and third
+        let equations = parse_lines::<usize>("190: 10 19\n190: 1 2\n83: 17 5\n83: 80 3".lines()).unwrap();
+        assert_eq!(equations.iter().map(|x| (x.line, x.target)).collect::<Vec<_>>(), vec![(1, 190), (2, 190), (3, 83), (4, 83)]);
+        assert_eq!(equations[1].values, vec![1, 2]);
+        assert_eq!(calibration_total(&equations, ADD_MULTIPLY).unwrap(), 190 + 83);
+
+        let equations = parse_lines::<usize>("190: 10 19\n190: 10 19".lines()).unwrap();
+        assert_eq!(calibration_total(&equations, ADD_MULTIPLY).unwrap(), 380);
     }
 
     #[rstest]
@@ -142,7 +666,7 @@ mod tests {
     #[case("21037: 9 7 18 13", (21037, vec![9, 7, 18, 13]))]
     #[case("292: 11 6 16 20", (292, vec![11, 6, 16, 20]))]
     fn test_parse_line(#[case] input: &str, #[case] expected: (usize, Vec<usize>)) {
-        assert_eq!(parse_line(input), expected);
+        assert_eq!(parse_line(input).unwrap(), expected);
     }
 
     #[rstest]
@@ -156,7 +680,8 @@ mod tests {
     #[case(vec![9, 7, 18, 13], 21037, false)]
     #[case(vec![11, 6, 16, 20], 292, true)]
     fn test_operate_no_concat(#[case] values: Vec<usize>, #[case] target: usize, #[case] valid: bool) {
-        assert_eq!(operate(vec![], &values, target, false), if valid { target } else { 0 });
+        assert_eq!(operate(vec![], &values, target, ADD_MULTIPLY), if valid { target } else { 0 });
+        assert_eq!(solvable(&values, &target, ADD_MULTIPLY).unwrap(), valid);
     }
 
     #[rstest]
@@ -170,7 +695,8 @@ mod tests {
     #[case(vec![9, 7, 18, 13], 21037, false)]
     #[case(vec![11, 6, 16, 20], 292, true)]
     fn test_operate_concat(#[case] values: Vec<usize>, #[case] target: usize, #[case] valid: bool) {
-        assert_eq!(operate(vec![], &values, target, true), if valid { target } else { 0 });
+        assert_eq!(operate(vec![], &values, target, ADD_MULTIPLY_CONCATENATE), if valid { target } else { 0 });
+        assert_eq!(solvable(&values, &target, ADD_MULTIPLY_CONCATENATE).unwrap(), valid);
     }
 
     #[rstest]
@@ -185,7 +711,287 @@ mod tests {
     #[case(11, 6, 116)]
     #[case(11, 0, 110)]
     fn test_concat_digits(#[case] lhs: usize, #[case] rhs: usize, #[case] expected: usize) {
-        assert_eq!(concat_digits(lhs, rhs), expected);
+        assert_eq!(concat_digits(&lhs, &rhs, 10), Some(expected));
+    }
+
+    #[rstest]
+    #[case(0b10, 0b11, 2, 0b1011)]
+    #[case(0b1, 0b0, 2, 0b10)]
+    #[case(0o7, 0o10, 8, 0o710)]
+    #[case(0xf, 0xab, 16, 0xfab)]
+    fn test_concat_digits_base(#[case] lhs: usize, #[case] rhs: usize, #[case] base: usize, #[case] expected: usize) {
+        assert_eq!(concat_digits(&lhs, &rhs, base), Some(expected));
+    }
+
+    #[test]
+    fn test_concat_digits_overflow() {
+        assert_eq!(concat_digits(&(usize::MAX / 10), &10, 10), None);
+        assert_eq!(concat_digits(&1, &usize::MAX, 10), None);
+    }
+
+    #[rstest]
+    #[case(&Add, 7, 5, Some(12), Inverse::One(7))]
+    #[case(&Multiply, 7, 5, Some(35), Inverse::One(7))]
+    #[case(&Multiply, 7, 0, Some(0), Inverse::Any)]
+    #[case(&Concatenate { base: 10 }, 7, 5, Some(75), Inverse::One(7))]
+    #[case(&Concatenate { base: 10 }, 0, 5, Some(5), Inverse::One(0))]
+    #[case(&Concatenate { base: 2 }, 3, 2, Some(14), Inverse::One(3))]
+    #[case(&Subtract, 7, 5, Some(2), Inverse::One(7))]
+    #[case(&Subtract, 5, 7, None, Inverse::None)]
+    #[case(&Power, 7, 5, Some(16807), Inverse::One(7))]
+    #[case(&Power, 7, 0, Some(1), Inverse::Any)]
+    fn test_apply_inverse(#[case] operator: &dyn Operator, #[case] lhs: usize, #[case] rhs: usize, #[case] result: Option<usize>, #[case] inverse: Inverse) {
+        assert_eq!(operator.apply(&lhs, &rhs).unwrap(), result);
+        if let Some(result) = result {
+            assert_eq!(operator.inverse(&result, &rhs).unwrap(), inverse);
+        }
     }
 
-}
\ No newline at end of file
+    #[rstest]
+    #[case(&Add, usize::MAX, 1)]
+    #[case(&Multiply, usize::MAX / 2, 3)]
+    #[case(&Concatenate { base: 10 }, usize::MAX / 10, 10)]
+    #[case(&Power, 10, 20)]
+    #[case(&Power, 2, 1 << 40)]
+    fn test_apply_overflow(#[case] operator: &dyn Operator, #[case] lhs: usize, #[case] rhs: usize) {
+        assert!(matches!(operator.apply(&lhs, &rhs), Err(Errors::Overflow(_))));
+    }
+
+    #[rstest]
+    #[case((1 << 60) + 100, 1, Inverse::One((1 << 60) + 100))]
+    #[case(usize::MAX, 1, Inverse::One(usize::MAX))]
+    #[case(4294967295 * 4294967295, 2, Inverse::One(4294967295))]
+    #[case(4294967295 * 4294967295 + 1, 2, Inverse::None)]
+    #[case(3usize.pow(40), 40, Inverse::One(3))]
+    #[case(3usize.pow(40) - 1, 40, Inverse::None)]
+    #[case(2usize.pow(63), 63, Inverse::One(2))]
+    #[case(0, 7, Inverse::One(0))]
+    fn test_power_inverse(#[case] result: usize, #[case] rhs: usize, #[case] expected: Inverse) {
+        assert_eq!(Power.inverse(&result, &rhs).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_evaluates_overflow() {
+        // 10 ^ 20 doesn't fit, but 10 + 20 does, so multiplying by zero still gives 0
+        let operators: &[&dyn Operator] = &[&Add, &Power, &Multiply];
+        assert!(evaluates(&[10, 20, 0], operators).unwrap());
+        assert!(solvable(&[10, 20, 0], &0, operators).unwrap());
+        let found = solutions(&[10, 20, 0], &0, operators, usize::MAX).unwrap();
+        assert_eq!(found.iter().map(|x| x.to_string()).sorted().collect_vec(), vec!["10 * 20 * 0", "10 + 20 * 0"]);
+        // only when nothing fits is it an error
+        assert!(matches!(evaluates(&[10, 20, 30], &[&Power as &dyn Operator]), Err(Errors::Overflow(_))));
+    }
+
+    #[test]
+    fn test_inverse_overflow() {
+        assert!(matches!(Subtract.inverse(&usize::MAX, &1), Err(Errors::Overflow(_))));
+        // more digits than the base to that power can hold, so only 0 || rhs gives rhs
+        let concatenate = Concatenate { base: 10 };
+        assert_eq!(Operator::<usize>::inverse(&concatenate, &usize::MAX, &usize::MAX).unwrap(), Inverse::One(0));
+        assert_eq!(Operator::<usize>::inverse(&concatenate, &(usize::MAX - 1), &usize::MAX).unwrap(), Inverse::None);
+        assert_eq!(Power.inverse(&1, &(1 << 40)).unwrap(), Inverse::One(1));
+        assert_eq!(Power.inverse(&2, &(1 << 40)).unwrap(), Inverse::None);
+    }
+
+    #[test]
+    fn test_search_inverse_overflow() {
+        // undoing - overflows, but undoing * doesn't
+        let operators: &[&dyn Operator] = &[&Subtract, &Multiply];
+        assert!(solvable(&[usize::MAX, 1], &usize::MAX, operators).unwrap());
+        let found = solutions(&[usize::MAX, 1], &usize::MAX, operators, usize::MAX).unwrap();
+        assert_eq!(found.iter().map(|x| x.to_string()).collect_vec(), vec![format!("{} * 1", usize::MAX)]);
+        // the overflow is further back, after undoing - for the last value, and 4 + (max - 10) + 1 works
+        let operators: &[&dyn Operator] = &[&Subtract, &Add];
+        assert!(solvable(&[4, usize::MAX - 10, 1], &(usize::MAX - 5), operators).unwrap());
+        assert_eq!(solutions(&[4, usize::MAX - 10, 1], &(usize::MAX - 5), operators, usize::MAX).unwrap().len(), 1);
+        // only when nothing else works is it an error
+        let operators: &[&dyn Operator] = &[&Subtract];
+        assert!(matches!(solvable(&[usize::MAX, 1], &usize::MAX, operators), Err(Errors::Overflow(_))));
+        assert!(matches!(solutions(&[usize::MAX, 1], &usize::MAX, operators, usize::MAX), Err(Errors::Overflow(_))));
+    }
+
+    #[rstest]
+    #[case(&Add, 12, 13)]
+    #[case(&Multiply, 36, 5)]
+    #[case(&Multiply, 5, 0)]
+    #[case(&Concatenate { base: 10 }, 75, 6)]
+    #[case(&Concatenate { base: 10 }, 5, 15)]
+    #[case(&Power, 17, 2)]
+    #[case(&Power, 0, 0)]
+    fn test_no_inverse(#[case] operator: &dyn Operator, #[case] result: usize, #[case] rhs: usize) {
+        assert_eq!(operator.inverse(&result, &rhs).unwrap(), Inverse::None);
+    }
+
+    #[rstest]
+    // 10 - 3 = 7, which can't be made with + and *
+    #[case(vec![10, 3], 7, vec![&Add as &dyn Operator, &Multiply, &Subtract], true)]
+    #[case(vec![10, 3], 7, vec![&Add as &dyn Operator, &Multiply], false)]
+    // past the target before coming back down: 5 * 4 - 10 = 10
+    #[case(vec![5, 4, 10], 10, vec![&Multiply as &dyn Operator, &Subtract], true)]
+    // 2 ^ 3 + 1 = 9
+    #[case(vec![2, 3, 1], 9, vec![&Add as &dyn Operator, &Power], true)]
+    // 0b1 || 0b1 || 0b0 = 0b110
+    #[case(vec![1, 1, 0], 6, vec![&Concatenate { base: 2 } as &dyn Operator], true)]
+    #[case(vec![1, 1, 0], 110, vec![&Concatenate { base: 2 } as &dyn Operator], false)]
+    fn test_operate_operator_sets(#[case] values: Vec<usize>, #[case] target: usize, #[case] operators: Vec<&dyn Operator>, #[case] valid: bool) {
+        assert_eq!(operate(vec![], &values, target, &operators), if valid { target } else { 0 });
+        assert_eq!(solvable(&values, &target, &operators).unwrap(), valid);
+    }
+
+    #[rstest]
+    // multiplying by zero can bring a total past the target back down
+    #[case(vec![20, 3, 0], 0, true)]
+    #[case(vec![20, 3, 0, 4], 4, true)]
+    #[case(vec![20, 3, 0, 4], 5, false)]
+    #[case(vec![0, 0], 0, true)]
+    #[case(vec![7], 7, true)]
+    #[case(vec![7], 8, false)]
+    #[case(vec![], 0, false)]
+    fn test_solvable_zeros(#[case] values: Vec<usize>, #[case] target: usize, #[case] valid: bool) {
+        assert_eq!(solvable(&values, &target, ADD_MULTIPLY_CONCATENATE).unwrap(), valid);
+        if target > 0 {
+            assert_eq!(operate(vec![], &values, target, ADD_MULTIPLY_CONCATENATE) == target, valid);
+        }
+    }
+
+    #[test]
+    fn test_evaluates() {
+        assert!(!evaluates::<usize>(&[1, 2, 3], &[&Subtract]).unwrap());
+        assert!(evaluates::<usize>(&[5, 2, 3], &[&Subtract]).unwrap());
+        assert!(evaluates::<usize>(&[1, 2, 3], &[&Subtract, &Add]).unwrap());
+        assert!(!evaluates(&[], ADD_MULTIPLY).unwrap());
+        // anything to the power 0 is 1, as long as there's something
+        assert!(solvable(&[2, 3, 0], &1, &[&Subtract as &dyn Operator, &Power]).unwrap());
+        assert!(!solvable(&[2, 3, 0], &2, &[&Subtract as &dyn Operator, &Power]).unwrap());
+        assert!(!solvable(&[2, 3, 0], &1, &[&Subtract as &dyn Operator, &Multiply]).unwrap());
+    }
+
+    /// Random equations, half of them made from a random choice of operators so they're solvable.
+    fn generate_equations(count: usize, length: usize, max_value: usize, operators: &[&dyn Operator], seed: u64) -> Vec<(usize, Vec<usize>)> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..count).map(|_| {
+            let values: Vec<usize> = (0..length).map(|_| rng.gen_range(0..=max_value)).collect();
+            let target = if rng.gen_bool(0.5) {
+                values[1..].iter()
+                    .try_fold(values[0], |total, x| operators[rng.gen_range(0..operators.len())].apply(&total, x).ok().flatten())
+                    .unwrap_or(0)
+            } else {
+                rng.gen_range(0..max_value.pow(length as u32))
+            };
+            (target, values)
+        }).collect()
+    }
+
+    #[test]
+    fn test_solvable_matches_forward() {
+        let operator_sets: Vec<Vec<&dyn Operator>> = vec![
+            ADD_MULTIPLY.to_vec(),
+            ADD_MULTIPLY_CONCATENATE.to_vec(),
+            vec![&Add, &Subtract, &Multiply],
+            vec![&Add, &Power],
+            vec![&Concatenate { base: 2 }, &Multiply],
+        ];
+        for (seed, operators) in operator_sets.iter().enumerate() {
+            // the forward search gives 0 for unsolvable equations, so can't check a target of 0
+            for (target, values) in generate_equations(300, 5, 9, operators, seed as u64).into_iter().filter(|(target, _)| *target > 0) {
+                assert_eq!(solvable(&values, &target, operators).unwrap(), operate(vec![], &values, target, operators) == target, "{}: {:?} with {}", target, values, operators.iter().map(|x| x.symbol()).join(""));
+            }
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_` to see the timings.
+    #[test]
+    #[ignore]
+    fn bench_solvable() {
+        let equations = generate_equations(50, 14, 99, ADD_MULTIPLY_CONCATENATE, 1);
+
+        let start = Instant::now();
+        let forward: usize = equations.iter().map(|(target, values)| operate(vec![], values, *target, ADD_MULTIPLY_CONCATENATE)).sum();
+        let forward_time = start.elapsed();
+
+        let start = Instant::now();
+        let backward: usize = equations.iter().filter(|(target, values)| solvable(values, target, ADD_MULTIPLY_CONCATENATE).unwrap()).map(|(target, _)| target).sum();
+        let backward_time = start.elapsed();
+
+        assert_eq!(forward, backward);
+        println!("{} equations of 14 values: forward {:?}, backward {:?}", equations.len(), forward_time, backward_time);
+    }
+
+    #[rstest]
+    #[case(vec![10, 19], 190, ADD_MULTIPLY, vec!["10 * 19"])]
+    #[case(vec![81, 40, 27], 3267, ADD_MULTIPLY, vec!["81 * 40 + 27", "81 + 40 * 27"])]
+    #[case(vec![11, 6, 16, 20], 292, ADD_MULTIPLY, vec!["11 + 6 * 16 + 20"])]
+    #[case(vec![17, 5], 83, ADD_MULTIPLY_CONCATENATE, vec![])]
+    #[case(vec![15, 6], 156, ADD_MULTIPLY_CONCATENATE, vec!["15 || 6"])]
+    #[case(vec![6, 8, 6, 15], 7290, ADD_MULTIPLY_CONCATENATE, vec!["6 * 8 || 6 * 15"])]
+    #[case(vec![17, 8, 14], 192, ADD_MULTIPLY_CONCATENATE, vec!["17 || 8 + 14"])]
+    #[case(vec![7], 7, ADD_MULTIPLY_CONCATENATE, vec!["7"])]
+    #[case(vec![1, 1, 1], 1, ADD_MULTIPLY, vec!["1 * 1 * 1"])]
+    #[case(vec![2, 2, 2], 8, ADD_MULTIPLY, vec!["2 * 2 * 2", "2 + 2 * 2"])]
+    // multiplying by zero at the end means any operators before it work
+    #[case(vec![3, 4, 0], 0, ADD_MULTIPLY, vec!["3 * 4 * 0", "3 + 4 * 0"])]
+    fn test_solutions(#[case] values: Vec<usize>, #[case] target: usize, #[case] operators: &[&dyn Operator], #[case] expected: Vec<&str>) {
+        let found = solutions(&values, &target, operators, usize::MAX).unwrap();
+        let mut rendered: Vec<String> = found.iter().map(|x| x.to_string()).collect();
+        rendered.sort();
+        assert_eq!(rendered, expected);
+        assert!(found.iter().all(|x| x.evaluate().unwrap() == Some(target)));
+        assert_eq!(solutions(&values, &target, operators, 1).unwrap().len(), expected.len().min(1));
+    }
+
+    #[test]
+    fn test_solutions_match_solvable() {
+        for (target, values) in generate_equations(300, 5, 9, ADD_MULTIPLY_CONCATENATE, 7) {
+            let found = solutions(&values, &target, ADD_MULTIPLY_CONCATENATE, usize::MAX).unwrap();
+            assert_eq!(!found.is_empty(), solvable(&values, &target, ADD_MULTIPLY_CONCATENATE).unwrap());
+            // every choice of operators that works, and no others
+            let expected = (1..values.len()).map(|_| ADD_MULTIPLY_CONCATENATE.iter().copied()).multi_cartesian_product()
+                .filter(|x| Expression { values: &values, operators: x.clone() }.evaluate().unwrap() == Some(target))
+                .count();
+            assert_eq!(found.len(), expected, "{}: {:?}", target, values);
+        }
+    }
+
+    #[test]
+    fn test_expression_display() {
+        let expression = Expression { values: &[81, 40, 27], operators: vec![&Add, &Multiply] };
+        assert_eq!(expression.to_string(), "81 + 40 * 27");
+        assert_eq!(expression.evaluate().unwrap(), Some(3267));
+        assert_eq!(Expression { values: &[12, 345], operators: vec![&Concatenate { base: 10 }] }.to_string(), "12 || 345");
+    }
+
+    #[test]
+    fn test_overflow() {
+        let big = "18446744073709551616: 1 2";
+        assert!(matches!(parse_lines::<usize>(big.lines()), Err(Errors::Overflow(_))));
+        assert!(matches!(parse_lines::<usize>("12x: 1 2".lines()), Err(Errors::ParseFailed(_))));
+        // each target fits, but their sum doesn't
+        let equations = parse_lines::<usize>(format!("{}: {}\n{}: {}", usize::MAX, usize::MAX, 1, 1).lines()).unwrap();
+        assert!(matches!(calibration_total(&equations, ADD_MULTIPLY), Err(Errors::Overflow(_))));
+        assert!(matches!(calibrate::<usize>(big, false), Err(Errors::Overflow(_))));
+        // overflowing on the way to the target is an error rather than a wrapped result
+        assert!(matches!(Expression { values: &[usize::MAX, 2], operators: vec![&Multiply] }.evaluate(), Err(Errors::Overflow(_))));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint() {
+        use crate::day07::describe;
+        use num_bigint::BigUint;
+
+        assert_eq!(calibrate::<BigUint>(TEST_INPUT, false).unwrap(), "3749");
+        assert_eq!(calibrate::<BigUint>(TEST_INPUT, true).unwrap(), "11387");
+        // 2^64 and more
+        let big = "18446744073709551616: 4294967296 4294967296\n\
+                   1844674407370955161618446744073709551616: 18446744073709551616 18446744073709551616\n\
+                   36893488147419103232: 18446744073709551616 2";
+        assert_eq!(calibrate::<BigUint>(big, false).unwrap(), "55340232221128654848");
+        assert_eq!(calibrate::<BigUint>(big, true).unwrap(), "1844674407370955161673786976294838206464");
+        // the inverses that need a root or a long suffix
+        assert_eq!(Power.inverse(&BigUint::from(3usize).pow(50), &BigUint::from(50usize)).unwrap(), Inverse::One(BigUint::from(3usize)));
+        assert_eq!(Power.inverse(&(BigUint::from(3usize).pow(50) + 1usize), &BigUint::from(50usize)).unwrap(), Inverse::None);
+        // the details only fit with arbitrary precision too
+        assert!(matches!(describe::<usize>(big), Err(Errors::Overflow(_))));
+        assert_eq!(describe::<BigUint>(big).unwrap().lines().next().unwrap(), "line 1: 18446744073709551616 = 4294967296 * 4294967296 (1 solution)");
+    }
+}
@@ -1,7 +1,7 @@
 use crate::util::{load_from, Errors};
 use crate::Day;
 use core::str::Lines;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use itertools::Itertools;
 
 pub struct Day08 {}
@@ -17,6 +17,23 @@ impl Day for Day08 {
         run(part_2_antinodes)
     }
 
+    fn details(&self) -> Option<Result<String, Errors>> {
+        Some(load_from("day08a.txt").map(|str| {
+            let (map, row_max, col_max) = parse_lines(str.lines());
+            let by_frequency = antinodes_by_frequency(part_2_antinodes, &map, row_max, col_max);
+            let all: HashSet<Coord> = by_frequency.values().flat_map(|x| x.keys().copied()).collect();
+            let breakdown = by_frequency.iter()
+                .map(|(frequency, antinodes)| {
+                    let sources = antinodes.iter()
+                        .map(|(antinode, pairs)| format!("  {:?} from {}", antinode, pairs.iter().map(|(a, b)| format!("{:?}-{:?}", a, b)).join(", ")))
+                        .join("\n");
+                    format!("{}: {} antinodes\n{}", frequency, antinodes.len(), sources)
+                })
+                .join("\n");
+            format!("{}\n{}", render(&map, &all, row_max, col_max), breakdown)
+        }))
+    }
+
     fn create_day() -> Box<dyn Day> where Self: Sized {
         Box::new(Day08 {})
     }
@@ -115,13 +132,43 @@ fn collect_antinodes(func: AntinodeFn, all_antennas: &HashMap<char, Vec<Coord>>,
         .collect()
 }
 
+/// The antinodes for each frequency, each with the pairs of antennas that produce it.
+fn antinodes_by_frequency(func: AntinodeFn, all_antennas: &HashMap<char, Vec<Coord>>, row_max: isize, col_max: isize) -> BTreeMap<char, BTreeMap<Coord, Vec<(Coord, Coord)>>> {
+    all_antennas.iter()
+        .map(|(frequency, coords)| {
+            let mut antinodes: BTreeMap<Coord, Vec<(Coord, Coord)>> = BTreeMap::new();
+            for (first, second) in coords.iter().tuple_combinations() {
+                // part 2 steps from both ends, so can reach the same cell twice for a pair
+                for antinode in func(first, second, row_max, col_max).into_iter().unique() {
+                    antinodes.entry(antinode).or_default().push((*first, *second));
+                }
+            }
+            (*frequency, antinodes)
+        })
+        .collect()
+}
+
+/// The map as the puzzle draws it, with `#` for antinodes that aren't under an antenna.
+fn render(all_antennas: &HashMap<char, Vec<Coord>>, antinodes: &HashSet<Coord>, row_max: isize, col_max: isize) -> String {
+    let mut grid = vec![vec!['.'; col_max as usize]; row_max as usize];
+    for antinode in antinodes {
+        grid[antinode.0 as usize][antinode.1 as usize] = '#';
+    }
+    for (frequency, coords) in all_antennas {
+        for coord in coords {
+            grid[coord.0 as usize][coord.1 as usize] = *frequency;
+        }
+    }
+    grid.iter().map(|row| row.iter().collect::<String>()).join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::{HashMap, HashSet};
     use itertools::Itertools;
     use lazy_static::lazy_static;
     use rstest::rstest;
-    use crate::day08::{collect_antinodes, find_antinodes_for_antenna, parse_lines, part_1_antinodes, part_2_antinodes, Coord};
+    use crate::day08::{antinodes_by_frequency, collect_antinodes, find_antinodes_for_antenna, parse_lines, part_1_antinodes, part_2_antinodes, render, Coord};
 
     const TEST_INPUT: &str = "............\n\
                               ........0...\n\
@@ -233,4 +280,50 @@ mod tests {
         )
     }
 
-}
\ No newline at end of file
+    const TEST_RENDERED: &str = "......#....#\n\
+                                 ...#....0...\n\
+                                 ....#0....#.\n\
+                                 ..#....0....\n\
+                                 ....0....#..\n\
+                                 .#....A.....\n\
+                                 ...#........\n\
+                                 #......#....\n\
+                                 ........A...\n\
+                                 .........A..\n\
+                                 ..........#.\n\
+                                 ..........#.";
+
+    #[test]
+    fn test_render() {
+        let antinodes = collect_antinodes(part_1_antinodes, &TEST_PARSED, 12, 12);
+        assert_eq!(render(&TEST_PARSED, &antinodes, 12, 12), TEST_RENDERED);
+    }
+
+    #[test]
+    fn test_render_pt2() {
+        let antennas = HashMap::from([('T', vec![(0, 0), (1, 3), (2, 1)])]);
+        let antinodes = collect_antinodes(part_2_antinodes, &antennas, 10, 10);
+        assert_eq!(render(&antennas, &antinodes, 10, 10), "T....#....\n...T......\n.T....#...\n.........#\n..#.......\n..........\n...#......\n..........\n....#.....\n..........");
+        assert_eq!(antinodes.len(), 9);
+    }
+
+    #[test]
+    fn test_antinodes_by_frequency() {
+        let by_frequency = antinodes_by_frequency(part_1_antinodes, &TEST_PARSED, 12, 12);
+        assert_eq!(by_frequency.keys().collect_vec(), vec![&'0', &'A']);
+        for (frequency, antinodes) in &by_frequency {
+            assert_eq!(antinodes.keys().copied().collect::<HashSet<Coord>>(), find_antinodes_for_antenna(part_1_antinodes, &TEST_PARSED[frequency], 12, 12));
+        }
+        // (1, 3) is an antinode of both frequencies
+        assert_eq!(by_frequency[&'A'][&(1, 3)], vec![((5, 6), (9, 9))]);
+        assert_eq!(by_frequency[&'0'][&(1, 3)], vec![((2, 5), (3, 7))]);
+        assert_eq!(by_frequency[&'A'][&(7, 7)], vec![((8, 8), (9, 9))]);
+
+        let by_frequency = antinodes_by_frequency(part_2_antinodes, &TEST_PARSED, 12, 12);
+        let all: HashSet<Coord> = by_frequency.values().flat_map(|x| x.keys().copied()).collect();
+        assert_eq!(all, collect_antinodes(part_2_antinodes, &TEST_PARSED, 12, 12));
+        // every antenna in line with another is an antinode of that pair
+        assert_eq!(by_frequency[&'A'][&(8, 8)], vec![((5, 6), (8, 8)), ((8, 8), (9, 9))]);
+        assert_eq!(by_frequency[&'A'][&(0, 0)], vec![((8, 8), (9, 9))]);
+    }
+}
@@ -31,6 +31,18 @@ mod day11;
 trait Day {
     fn part_1(&self) -> Result<String, Errors>;
     fn part_2(&self) -> Result<String, Errors>;
+
+    /// Extra output explaining the answers, shown when running with `--verbose`.
+    fn details(&self) -> Option<Result<String, Errors>> {
+        None
+    }
+
+    /// Draws how the answers are found step by step in the terminal, shown when running with
+    /// `--animate`.
+    fn animate(&self) -> Option<Result<(), Errors>> {
+        None
+    }
+
     fn create_day() -> Box<dyn Day> where Self: Sized;
 }
 
@@ -76,6 +88,20 @@ fn main() -> ExitCode {
                 Ok(answer) => println!("--> Part 2: {}", answer),
                 Err(err) => println!("--> Part 2 error: {:?}", err),
             }
+
+            if args.verbose {
+                match available_days.get(&day).unwrap().details() {
+                    Some(Ok(details)) => println!("--> Details:\n{}", details),
+                    Some(Err(err)) => println!("--> Details error: {:?}", err),
+                    None => {}
+                }
+            }
+
+            if args.animate {
+                if let Some(Err(err)) = available_days.get(&day).unwrap().animate() {
+                    println!("--> Animation error: {:?}", err);
+                }
+            }
         }
 
         ExitCode::SUCCESS
@@ -87,5 +113,13 @@ struct Args {
     days: Vec<u8>,
 
     #[arg(short, long)]
-    all: bool
+    all: bool,
+
+    /// Print extra details about how the answers were found, for days that support it
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Animate how the answers were found in the terminal, for days that support it
+    #[arg(long)]
+    animate: bool
 }
@@ -1,18 +1,29 @@
 use crate::util::Errors::FileLoadFailed;
 use std::fs;
-use std::path::Path;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 
 pub fn load_from(filename: &str) -> Result<String, Errors> {
-    let path = format!("data{}{}", std::path::MAIN_SEPARATOR, filename);
-    let data_file = Path::new(path.as_str());
-    fs::read_to_string(data_file).map_err(|x| FileLoadFailed(x))
+    fs::read_to_string(data_path(filename)).map_err(FileLoadFailed)
+}
+
+/// Opens a data file for streaming, for inputs large enough that we don't want them in one `String`.
+pub fn open_from(filename: &str) -> Result<BufReader<File>, Errors> {
+    File::open(data_path(filename)).map(BufReader::new).map_err(FileLoadFailed)
+}
+
+fn data_path(filename: &str) -> PathBuf {
+    Path::new("data").join(filename)
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum Errors {
     FileLoadFailed(std::io::Error),
+    ParseFailed(String),
     NoImplementationError,
-    BadResult(String)
+    BadResult(String),
+    /// A number didn't fit in the type it was being calculated in
+    Overflow(String)
 }
-